@@ -0,0 +1,22 @@
+//! Compiles `keymap.toml` into the default layers of `layouts_def`
+//! (`$OUT_DIR/keymap.rs`) and draws them in `$OUT_DIR/keymap.txt`. The file
+//! is read by `keymap-tool`, which the host tool shares.
+
+use keymap_tool::Keymap;
+use std::path::Path;
+use std::{env, fs, process};
+
+const KEYMAP_FILE: &str = "keymap.toml";
+
+fn main() {
+    println!("cargo:rerun-if-changed={KEYMAP_FILE}");
+    let keymap = Keymap::load(Path::new(KEYMAP_FILE)).unwrap_or_else(|error| {
+        eprintln!("error: {error}");
+        process::exit(1);
+    });
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let out_dir = Path::new(&out_dir);
+    fs::write(out_dir.join("keymap.rs"), keymap.rust_table()).unwrap();
+    fs::write(out_dir.join("keymap.txt"), keymap.draw()).unwrap();
+}
@@ -0,0 +1,144 @@
+//! Hardware a keyboard half runs on. The firmwares are written against these
+//! traits, a port to another board or MCU implements them next to the
+//! existing boards instead of forking the `main.rs` of a half.
+//!
+//! [`Board`] is what both halves need: the matrix, the UART link between the
+//! halves, a delay and the status LED. The half plugged into the host is also
+//! a [`HostBoard`], with USB and the flash the settings and the keymap are
+//! stored in.
+//!
+//! The firmwares run their tasks on RTIC, which keeps SysTick for its time
+//! base and binds the interrupts of the link and USB itself.
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{InputPin, OutputPin, PinState};
+use embedded_hal_nb::serial;
+use embedded_storage::nor_flash::NorFlash;
+use usb_device::bus::{UsbBus, UsbBusAllocator};
+
+/// Columns of the matrix of a half, powered one at a time
+pub const COLUMNS: usize = 5;
+/// Rows of the matrix of a half, read while a column is powered
+pub const ROWS: usize = 6;
+
+/// Time the rows get to settle after their column is powered
+const SETTLE_US: u32 = 10;
+
+pub trait Board: Sized {
+    type Column: OutputPin;
+    /// Pulled down, high while the key of the powered column is pressed
+    type Row: InputPin;
+    type LedPin: OutputPin;
+    /// UART to the other half
+    type LinkTx: serial::Write<u8>;
+    /// Raises its interrupt for every received byte
+    type LinkRx: serial::Read<u8>;
+    /// Busy waiting, for the matrix to settle. SysTick is taken
+    type Delay: DelayNs;
+
+    /// Core clock, which SysTick counts
+    const SYSCLK_HZ: u32;
+
+    /// Sets up the clocks and the peripherals, the first thing the
+    /// firmware does
+    fn init() -> Parts<Self>;
+
+    /// Resets into the bootloader the firmware is updated with
+    fn reboot_to_bootloader() -> !;
+}
+
+/// Peripherals of every half, from [`Board::init`]
+pub struct Parts<B: Board> {
+    pub matrix: Matrix<B::Column, B::Row>,
+    pub link_tx: B::LinkTx,
+    pub link_rx: B::LinkRx,
+    pub delay: B::Delay,
+    /// `None` on boards without an LED
+    pub led: Option<Led<B::LedPin>>,
+}
+
+/// The half plugged into the host
+pub trait HostBoard: Board {
+    type UsbBus: UsbBus;
+    /// Offsets count from [`shared_src::boot::FLASH_START`]
+    type Flash: NorFlash;
+
+    /// [`Board::init`] along with the peripherals of the host connection
+    fn init_host() -> (Parts<Self>, HostParts<Self>);
+
+    /// ID unique to the chip, the USB serial number is made of it
+    fn unique_id() -> &'static [u8];
+
+    /// Starts or stops signalling resume on the bus, which wakes up the
+    /// suspended host. The signal has to last 1 to 15 ms
+    fn signal_resume(on: bool);
+}
+
+/// Peripherals of the host connection, from [`HostBoard::init_host`]
+pub struct HostParts<B: HostBoard> {
+    pub usb_bus: UsbBusAllocator<B::UsbBus>,
+    pub flash: B::Flash,
+}
+
+/// Key matrix of a half, key `column * ROWS + row` sits between a column and
+/// a row
+pub struct Matrix<C, R> {
+    columns: [C; COLUMNS],
+    rows: [R; ROWS],
+}
+
+impl<C: OutputPin, R: InputPin> Matrix<C, R> {
+    pub fn new(columns: [C; COLUMNS], rows: [R; ROWS]) -> Self {
+        Self { columns, rows }
+    }
+
+    /// Bit of every pressed key set
+    pub fn scan(&mut self, delay: &mut impl DelayNs) -> u32 {
+        let mut pressed = 0;
+        for column in 0..COLUMNS {
+            pressed |= self.scan_column(column, delay) << (column * ROWS);
+        }
+        pressed
+    }
+
+    /// Whether key `index` is pressed, only its column is scanned
+    pub fn is_pressed(&mut self, index: usize, delay: &mut impl DelayNs) -> bool {
+        self.scan_column(index / ROWS, delay) & 1 << (index % ROWS) != 0
+    }
+
+    fn scan_column(&mut self, column: usize, delay: &mut impl DelayNs) -> u32 {
+        // GPIO of the supported boards can't fail, a pin that does reads as
+        // released
+        let power = &mut self.columns[column];
+        let _ = power.set_high();
+        delay.delay_us(SETTLE_US);
+        let mut pressed = 0;
+        for (row, signal) in self.rows.iter_mut().enumerate() {
+            if signal.is_high().unwrap_or(false) {
+                pressed |= 1 << row;
+            }
+        }
+        let _ = power.set_low();
+        pressed
+    }
+}
+
+/// Indicator LED, starts off
+pub struct Led<P> {
+    pin: P,
+    active_low: bool,
+}
+
+impl<P: OutputPin> Led<P> {
+    /// `active_low` for an LED lit while the pin is low, as the one on PC13
+    /// of the common STM32 boards
+    pub fn new(pin: P, active_low: bool) -> Self {
+        let mut led = Self { pin, active_low };
+        led.set(false);
+        led
+    }
+
+    pub fn set(&mut self, on: bool) {
+        let _ = self.pin.set_state(PinState::from(on != self.active_low));
+    }
+}
@@ -0,0 +1,110 @@
+use core::ops::{Deref, DerefMut};
+
+/// Returned by [`FixedVec::try_push`] with the rejected value when the vector is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityError<T>(pub T);
+
+/// Vector with a fixed capacity of `N` elements stored inline
+#[derive(Clone, Copy)]
+pub struct FixedVec<T, const N: usize> {
+    data: [T; N],
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> FixedVec<T, N> {
+    /// `default` is used only to initialise the unused storage
+    pub fn new(default: T) -> FixedVec<T, N> {
+        FixedVec {
+            data: [default; N],
+            len: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    pub fn try_push(&mut self, value: T) -> Result<(), CapacityError<T>> {
+        if self.len >= N {
+            return Err(CapacityError(value));
+        }
+
+        self.data[self.len] = value;
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.len -= 1;
+        Some(self.data[self.len])
+    }
+
+    /// Keeps only the elements for which `f` returns `true`, preserving order
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if f(&self.data[i]) {
+                self.data[kept] = self.data[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+impl<T: Copy + PartialEq, const N: usize> FixedVec<T, N> {
+    /// Set-like push: does nothing if `value` is already present
+    pub fn push_unique(&mut self, value: T) -> Result<(), CapacityError<T>> {
+        if self.contains(&value) {
+            return Ok(());
+        }
+
+        self.try_push(value)
+    }
+
+    /// Removes consecutive repeated elements in place, like `Vec::dedup`
+    pub fn dedup(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+        let mut kept = 1;
+        for i in 1..self.len {
+            if self.data[i] != self.data[kept - 1] {
+                self.data[kept] = self.data[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+impl<T, const N: usize> Deref for FixedVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.data[..self.len]
+    }
+}
+
+impl<T, const N: usize> DerefMut for FixedVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.data[..self.len]
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a FixedVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
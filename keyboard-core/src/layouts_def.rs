@@ -0,0 +1,521 @@
+// The engine state lives in `static mut`s, named like the locals they stand
+// for. Only the keymap task of the firmware touches them
+#![allow(non_upper_case_globals)]
+
+use crate::fixed_vec::FixedVec;
+use crate::mouse_keys::{MouseButton, MouseDirection, MouseHeld, MouseKeys};
+use crate::system_control::SystemControl;
+use shared_src::config_protocol::{KeyKind, KeyPosition, RawKey, Status};
+use shared_src::keymap_blob::KeymapShape;
+use shared_src::{HostLeds, PrimitiveBitset};
+use usbd_human_interface_device::{
+    page::{Consumer, Keyboard},
+};
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum MultiKey {
+    ConsumerKey(Consumer),
+    KeyboardKey(Keyboard),
+    SystemKey(SystemControl),
+    MouseMove(MouseDirection),
+    MouseButton(MouseButton),
+    MouseWheel(MouseDirection),
+    /// Acts once when pressed, see [`take_command`]
+    Command(KeyCommand),
+}
+
+/// Firmware actions bound to keys
+#[derive(Copy, Clone, PartialEq)]
+pub enum KeyCommand {
+    /// Switches between NKRO and 6KRO reports
+    ToggleKeyboardMode,
+    /// Reboots the left half into its bootloader
+    EnterBootloader,
+    /// Asks the right half to reboot into its ROM DFU bootloader
+    EnterRightBootloader,
+}
+
+impl KeyCommand {
+    pub fn from_code(code: u16) -> Option<Self> {
+        Some(match code {
+            0 => KeyCommand::ToggleKeyboardMode,
+            1 => KeyCommand::EnterBootloader,
+            2 => KeyCommand::EnterRightBootloader,
+            _ => return None,
+        })
+    }
+}
+
+/// How many keys a report may hold
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub enum KeyboardMode {
+    #[default]
+    Nkro,
+    /// Limited to what a boot protocol report holds, for BIOS screens and
+    /// KVM switches that only read the boot part of the report
+    SixKeyRollover,
+}
+
+impl MultiKey {
+    pub fn to_raw(self) -> RawKey {
+        match self {
+            MultiKey::KeyboardKey(key) => RawKey::new(KeyKind::Keyboard, u8::from(key) as u16),
+            MultiKey::ConsumerKey(key) => RawKey::new(KeyKind::Consumer, u16::from(key)),
+            MultiKey::SystemKey(key) => RawKey::new(KeyKind::System, key as u16),
+            MultiKey::MouseMove(direction) => RawKey::new(KeyKind::MouseMove, direction as u16),
+            MultiKey::MouseButton(button) => RawKey::new(KeyKind::MouseButton, button as u16),
+            MultiKey::MouseWheel(direction) => RawKey::new(KeyKind::MouseWheel, direction as u16),
+            MultiKey::Command(command) => RawKey::new(KeyKind::Command, command as u16),
+        }
+    }
+
+    pub fn from_raw(raw: RawKey) -> Option<Self> {
+        Some(match raw.kind {
+            KeyKind::Keyboard => MultiKey::KeyboardKey(Keyboard::from(u8::try_from(raw.code).ok()?)),
+            KeyKind::Consumer => MultiKey::ConsumerKey(Consumer::from(raw.code)),
+            KeyKind::System => MultiKey::SystemKey(SystemControl::from_code(raw.code)?),
+            KeyKind::MouseMove => MultiKey::MouseMove(MouseDirection::from_code(raw.code)?),
+            KeyKind::MouseButton => MultiKey::MouseButton(MouseButton::from_code(raw.code)?),
+            KeyKind::MouseWheel => MultiKey::MouseWheel(MouseDirection::from_code(raw.code)?),
+            KeyKind::Command => MultiKey::Command(KeyCommand::from_code(raw.code)?),
+        })
+    }
+}
+
+type KeybardMatrixLayout = [MultiKey; 30];
+
+const MATRIX_MASK: u32 = (1 << 30) - 1;
+
+const LEFT_LAYERS: usize = 2;
+const RIGHT_LAYERS: usize = 5;
+
+/// Shape of the keymap as serialised by `keymap_storage`
+pub const KEYMAP_SHAPE: KeymapShape = KeymapShape {
+    left_layers: LEFT_LAYERS as u8,
+    right_layers: RIGHT_LAYERS as u8,
+    layer_len: 30,
+};
+
+struct KeyboardLayout {
+    left: [KeybardMatrixLayout; LEFT_LAYERS],
+    right: [KeybardMatrixLayout; RIGHT_LAYERS],
+    overrides: &'static [KeyOverride],
+    led_layers: &'static [LedLayer],
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// While `layer` is active on `layer_side` and any of `keys` is held on
+/// `key_side`, `modifiers` are added to the ones already held
+struct KeyOverride {
+    layer_side: Side,
+    layer: usize,
+    key_side: Side,
+    keys: &'static [usize],
+    modifiers: &'static [Keyboard],
+}
+
+/// While the host `led` is lit and no layer key is held, `layer` becomes the
+/// base layer of `side`
+struct LedLayer {
+    led: u8,
+    side: Side,
+    layer: usize,
+}
+
+macro_rules! key {
+    ($key: ident) => {
+        MultiKey::KeyboardKey(Keyboard::$key)
+    };
+}
+
+macro_rules! consumer {
+    ($key: ident) => {
+        MultiKey::ConsumerKey(Consumer::$key)
+    };
+}
+
+macro_rules! system {
+    ($key: ident) => {
+        MultiKey::SystemKey(SystemControl::$key)
+    };
+}
+
+macro_rules! mouse_move {
+    ($direction: ident) => {
+        MultiKey::MouseMove(MouseDirection::$direction)
+    };
+}
+
+macro_rules! mouse_button {
+    ($button: ident) => {
+        MultiKey::MouseButton(MouseButton::$button)
+    };
+}
+
+macro_rules! mouse_wheel {
+    ($direction: ident) => {
+        MultiKey::MouseWheel(MouseDirection::$direction)
+    };
+}
+
+macro_rules! command {
+    ($command: ident) => {
+        MultiKey::Command(KeyCommand::$command)
+    };
+}
+
+const LEFT_FN: usize = 25;
+//const LEFT_SHIFT: usize = 18;
+
+const RIGHT_FN_1: usize = 26;
+const RIGHT_FN_2: usize = 27;
+const RIGHT_FN_3: usize = 28;
+
+// `LEFT_KEYMAP` and `RIGHT_KEYMAP`, compiled from keymap.toml by build.rs
+include!(concat!(env!("OUT_DIR"), "/keymap.rs"));
+
+#[rustfmt::skip]
+const KEYBOARD_LAYOUT: KeyboardLayout = KeyboardLayout {
+    left: LEFT_KEYMAP,
+    right: RIGHT_KEYMAP,
+    overrides: &[
+        // Meta + Alt + <left/right arrows>
+        KeyOverride {
+            layer_side: Side::Left, layer: 1,
+            key_side: Side::Right, keys: &[13, 16],
+            // If first was pressed an ALT and only after a GUI, ALT would be blocked
+            modifiers: &[Keyboard::LeftGUI, Keyboard::LeftAlt],
+        },
+        // Meta + Shift + <Home/End/PageUp/PageDown>
+        KeyOverride {
+            layer_side: Side::Left, layer: 1,
+            key_side: Side::Right, keys: &[19, 20, 21, 22],
+            modifiers: &[Keyboard::LeftGUI, Keyboard::LeftShift],
+        },
+    ],
+    led_layers: &[
+        // Numpad
+        LedLayer { led: HostLeds::NUM_LOCK, side: Side::Right, layer: 2 },
+    ],
+};
+
+/// Set of usages held in the reports being assembled. A usage produced by
+/// several sources (two positions or a special case) is reported once and
+/// stays pressed until the last of them is released
+struct ActiveUsages<'a> {
+    keys: &'a mut FixedVec<Keyboard, 58>,
+    media: &'a mut FixedVec<Consumer, 4>,
+    system: SystemControl,
+    mouse: MouseHeld,
+    key_overflow: bool,
+    media_overflow: bool,
+}
+
+impl<'a> ActiveUsages<'a> {
+    fn new(keys: &'a mut FixedVec<Keyboard, 58>, media: &'a mut FixedVec<Consumer, 4>) -> Self {
+        keys.clear();
+        media.clear();
+        Self {
+            keys,
+            media,
+            system: SystemControl::None,
+            mouse: MouseHeld::default(),
+            key_overflow: false,
+            media_overflow: false,
+        }
+    }
+
+    fn add(&mut self, key: MultiKey) {
+        match key {
+            MultiKey::KeyboardKey(Keyboard::NoEventIndicated) => {}
+            MultiKey::ConsumerKey(Consumer::Unassigned) => {}
+            MultiKey::KeyboardKey(key) => {
+                self.key_overflow |= self.keys.push_unique(key).is_err();
+            }
+            MultiKey::ConsumerKey(key) => {
+                self.media_overflow |= self.media.push_unique(key).is_err();
+            }
+            // The report holds a single usage, the first key wins
+            MultiKey::SystemKey(key) => {
+                if self.system == SystemControl::None {
+                    self.system = key;
+                }
+            }
+            MultiKey::MouseMove(direction) => self.mouse.move_to(direction),
+            MultiKey::MouseButton(button) => self.mouse.press_button(button),
+            MultiKey::MouseWheel(direction) => self.mouse.scroll_to(direction),
+            // Handled on the press edge only
+            MultiKey::Command(_) => {}
+        }
+    }
+
+    fn add_modifiers(&mut self, modifiers: &[Keyboard]) {
+        for &modifier in modifiers {
+            self.add(MultiKey::KeyboardKey(modifier));
+        }
+    }
+}
+
+/// Keymap used by the engine, starts as `KEYBOARD_LAYOUT` and can be edited
+/// through the configuration channel, `keymap_storage` keeps the edits
+static mut keymap: KeyboardLayout = KEYBOARD_LAYOUT;
+/// Set by every keymap edit, see [`take_keymap_changed`]
+static mut keymap_changed: bool = false;
+
+static mut rollovers: u32 = 0;
+static mut prev_leds: HostLeds = HostLeds(0);
+static mut prev_mode: KeyboardMode = KeyboardMode::Nkro;
+static mut pending_command: Option<KeyCommand> = None;
+static mut prev_left_layer: usize = 0;
+static mut prev_right_layer: usize = 0;
+static mut prev_left_matrix: PrimitiveBitset<u32> = PrimitiveBitset::new(0);
+static mut prev_right_matrix: PrimitiveBitset<u32> = PrimitiveBitset::new(0);
+static mut blocked_right_matrix: PrimitiveBitset<u32> = PrimitiveBitset::new(0);
+static mut blocked_left_matrix: PrimitiveBitset<u32> = PrimitiveBitset::new(0);
+
+/// Rebuilds the reports from the matrices. Returns `false` and leaves the
+/// reports untouched if neither a key, the host LEDs nor the mode changed
+/// since the previous call
+#[allow(clippy::too_many_arguments)]
+pub fn get_report(
+    left_matrix: PrimitiveBitset<u32>,
+    right_matrix: PrimitiveBitset<u32>,
+    leds: HostLeds,
+    mode: KeyboardMode,
+    key_report: &mut FixedVec<Keyboard, 58>,
+    media_report: &mut FixedVec<Consumer, 4>,
+    system_report: &mut SystemControl,
+    mouse_keys: &mut MouseKeys,
+) -> bool {
+    // Drop bits past the last key, e.g. from a corrupted UART message
+    let left_matrix = left_matrix & PrimitiveBitset::new(MATRIX_MASK);
+    let right_matrix = right_matrix & PrimitiveBitset::new(MATRIX_MASK);
+
+    let (left_diff, right_diff, leds_changed, mode_changed) = unsafe {
+        let (prev_left, prev_right) = (prev_left_matrix, prev_right_matrix);
+        let diffs = (
+            left_matrix.diff(&prev_left),
+            right_matrix.diff(&prev_right),
+            leds != prev_leds,
+            mode != prev_mode,
+        );
+        prev_left_matrix = left_matrix;
+        prev_right_matrix = right_matrix;
+        prev_leds = leds;
+        prev_mode = mode;
+        diffs
+    };
+    if left_diff.is_empty() && right_diff.is_empty() && !leds_changed && !mode_changed {
+        return false;
+    }
+
+    let prev_media_report = *media_report;
+    let mut usages = ActiveUsages::new(key_report, media_report);
+
+    let left_layer = get_left_layer(&left_matrix, leds);
+    let right_layer = if left_layer == 1 {
+        // For ergonomic meta + alt + <arrows>
+        1
+    } else {
+        get_right_layer(&right_matrix, leds)
+    };
+
+    unsafe {
+        // Keys held while the layer changes stay blocked until released
+        if left_layer != prev_left_layer {
+            prev_left_layer = left_layer;
+            blocked_left_matrix = left_matrix;
+        }
+        blocked_left_matrix = blocked_left_matrix & !left_diff.released;
+
+        if right_layer != prev_right_layer {
+            prev_right_layer = right_layer;
+            blocked_right_matrix = right_matrix;
+        }
+        blocked_right_matrix = blocked_right_matrix & !right_diff.released;
+
+        for i in (left_matrix & !blocked_left_matrix).iter_ones() {
+            usages.add(keymap.left[left_layer][i]);
+        }
+
+        for i in (right_matrix & !blocked_right_matrix).iter_ones() {
+            usages.add(keymap.right[right_layer][i]);
+        }
+
+        let pressed = (left_diff.pressed & !blocked_left_matrix)
+            .iter_ones()
+            .map(|i| keymap.left[left_layer][i])
+            .chain(
+                (right_diff.pressed & !blocked_right_matrix)
+                    .iter_ones()
+                    .map(|i| keymap.right[right_layer][i]),
+            );
+        for key in pressed {
+            if let MultiKey::Command(command) = key {
+                pending_command = Some(command);
+            }
+        }
+    }
+
+    for key_override in unsafe { keymap.overrides } {
+        let layer = match key_override.layer_side {
+            Side::Left => left_layer,
+            Side::Right => right_layer,
+        };
+        let matrix = match key_override.key_side {
+            Side::Left => left_matrix,
+            Side::Right => right_matrix,
+        };
+        if layer == key_override.layer && key_override.keys.iter().any(|&i| matrix.get(i)) {
+            usages.add_modifiers(key_override.modifiers);
+        }
+    }
+
+    let (mut key_overflow, media_overflow) = (usages.key_overflow, usages.media_overflow);
+    *system_report = usages.system;
+    mouse_keys.set_held(usages.mouse);
+
+    if mode == KeyboardMode::SixKeyRollover {
+        key_overflow |= key_report.iter().filter(|key| !is_modifier(key)).count() > 6;
+    }
+
+    if key_overflow {
+        unsafe { rollovers = rollovers.wrapping_add(1) };
+        // HID ErrorRollOver: modifiers stay valid, every other key is reported as an error
+        key_report.retain(is_modifier);
+        let _ = key_report.try_push(Keyboard::ErrorRollOver);
+    }
+
+    if media_overflow {
+        // The consumer page has no rollover usage, keep the last valid state instead
+        *media_report = prev_media_report;
+    }
+
+    true
+}
+
+/// Command of the last key pressed since the previous call
+pub fn take_command() -> Option<KeyCommand> {
+    unsafe {
+        let command = pending_command;
+        pending_command = None;
+        command
+    }
+}
+
+/// Matrices seen by the last `get_report` call
+pub fn matrix_state() -> (PrimitiveBitset<u32>, PrimitiveBitset<u32>) {
+    unsafe { (prev_left_matrix, prev_right_matrix) }
+}
+
+/// Left and right layers used by the last report
+pub fn active_layers() -> (usize, usize) {
+    unsafe { (prev_left_layer, prev_right_layer) }
+}
+
+/// Number of reports replaced by the rollover state since power-up
+pub fn rollover_count() -> u32 {
+    unsafe { rollovers }
+}
+
+pub fn get_key(position: KeyPosition) -> Result<MultiKey, Status> {
+    keymap_entry(position).map(|entry| *entry)
+}
+
+pub fn set_key(position: KeyPosition, key: MultiKey) -> Result<(), Status> {
+    *keymap_entry(position)? = key;
+    unsafe { keymap_changed = true };
+    Ok(())
+}
+
+/// Applies a keymap decoded by `keymap_blob` over the current one. Entries
+/// this firmware can't express or has no position for keep their key
+pub fn load_keymap(shape: KeymapShape, entries: impl Iterator<Item = Option<RawKey>>) {
+    for (i, raw) in entries.enumerate() {
+        let (side, layer, index) = shape.position(i);
+        if let Some(key) = raw.and_then(MultiKey::from_raw) {
+            let _ = set_key(KeyPosition { side, layer, index }, key);
+        }
+    }
+    // Loading isn't an edit that needs storing again
+    take_keymap_changed();
+}
+
+/// Restores the keymap built into the firmware
+pub fn reset_keymap() {
+    unsafe {
+        keymap = KEYBOARD_LAYOUT;
+        keymap_changed = true;
+    }
+}
+
+/// Whether the keymap was edited since the previous call
+pub fn take_keymap_changed() -> bool {
+    unsafe {
+        let changed = keymap_changed;
+        keymap_changed = false;
+        changed
+    }
+}
+
+fn keymap_entry(position: KeyPosition) -> Result<&'static mut MultiKey, Status> {
+    let keymap_ref = unsafe { &mut *core::ptr::addr_of_mut!(keymap) };
+    let layers: &mut [KeybardMatrixLayout] = match position.side {
+        0 => &mut keymap_ref.left,
+        1 => &mut keymap_ref.right,
+        _ => return Err(Status::InvalidArgument),
+    };
+    layers
+        .get_mut(position.layer as usize)
+        .and_then(|layer| layer.get_mut(position.index as usize))
+        .ok_or(Status::InvalidArgument)
+}
+
+fn is_modifier(key: &Keyboard) -> bool {
+    matches!(
+        key,
+        Keyboard::LeftControl
+            | Keyboard::LeftShift
+            | Keyboard::LeftAlt
+            | Keyboard::LeftGUI
+            | Keyboard::RightControl
+            | Keyboard::RightShift
+            | Keyboard::RightAlt
+            | Keyboard::RightGUI
+    )
+}
+
+fn get_left_layer(matrix: &PrimitiveBitset<u32>, leds: HostLeds) -> usize {
+    if matrix.get(LEFT_FN) {
+        1
+    } else {
+        get_led_layer(Side::Left, leds)
+    }
+}
+
+fn get_right_layer(matrix: &PrimitiveBitset<u32>, leds: HostLeds) -> usize {
+    // Tri-layer: both FN keys together switch to the mouse layer
+    if matrix.get(RIGHT_FN_1) && matrix.get(RIGHT_FN_2) {
+        4
+    } else if matrix.get(RIGHT_FN_1) {
+        1
+    } else if matrix.get(RIGHT_FN_2) {
+        2
+    } else if matrix.get(RIGHT_FN_3) {
+        3
+    } else {
+        get_led_layer(Side::Right, leds)
+    }
+}
+
+fn get_led_layer(side: Side, leds: HostLeds) -> usize {
+    unsafe { keymap.led_layers }
+        .iter()
+        .find(|led_layer| led_layer.side == side && leds.get(led_layer.led))
+        .map_or(0, |led_layer| led_layer.layer)
+}
@@ -0,0 +1,12 @@
+//! Keymap engine of the keyboard: turns the matrices of both halves into HID
+//! reports. It has no hardware dependencies, the left half runs it and the
+//! simulator tests it on the host. The hardware the halves run on is reached
+//! through the traits of [`board`].
+
+#![no_std]
+
+pub mod board;
+pub mod fixed_vec;
+pub mod layouts_def;
+pub mod mouse_keys;
+pub mod system_control;
@@ -0,0 +1,169 @@
+use usbd_human_interface_device::device::mouse::WheelMouseReport;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum MouseDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl MouseDirection {
+    const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    pub fn from_code(code: u16) -> Option<Self> {
+        Some(match code {
+            0 => MouseDirection::Up,
+            1 => MouseDirection::Down,
+            2 => MouseDirection::Left,
+            3 => MouseDirection::Right,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+}
+
+impl MouseButton {
+    const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    pub fn from_code(code: u16) -> Option<Self> {
+        Some(match code {
+            0 => MouseButton::Left,
+            1 => MouseButton::Right,
+            2 => MouseButton::Middle,
+            3 => MouseButton::Back,
+            4 => MouseButton::Forward,
+            _ => return None,
+        })
+    }
+}
+
+// Acceleration curve, all times are in ticks of the 1 kHz timer.
+// Cursor speed grows linearly from MOVE_MIN_SPEED to MOVE_MAX_SPEED over
+// MOVE_TIME_TO_MAX, the wheel scrolls one step per WHEEL_INTERVAL
+const MOVE_INTERVAL: u32 = 16;
+const MOVE_MIN_SPEED: u32 = 2;
+const MOVE_MAX_SPEED: u32 = 20;
+const MOVE_TIME_TO_MAX: u32 = 800;
+const WHEEL_INTERVAL: u32 = 60;
+
+/// Mouse keys state: what the keymap holds and how long it was held
+pub struct MouseKeys {
+    buttons: u8,
+    moving: u8,
+    wheel: u8,
+    held_ms: u32,
+    buttons_changed: bool,
+}
+
+/// Mouse actions collected while the keymap engine assembles a report
+#[derive(Default)]
+pub struct MouseHeld {
+    buttons: u8,
+    moving: u8,
+    wheel: u8,
+}
+
+impl MouseHeld {
+    pub fn press_button(&mut self, button: MouseButton) {
+        self.buttons |= button.bit();
+    }
+
+    pub fn move_to(&mut self, direction: MouseDirection) {
+        self.moving |= direction.bit();
+    }
+
+    pub fn scroll_to(&mut self, direction: MouseDirection) {
+        self.wheel |= direction.bit();
+    }
+}
+
+impl MouseKeys {
+    pub const fn new() -> Self {
+        Self {
+            buttons: 0,
+            moving: 0,
+            wheel: 0,
+            held_ms: 0,
+            buttons_changed: false,
+        }
+    }
+
+    pub fn set_held(&mut self, held: MouseHeld) {
+        if held.buttons != self.buttons {
+            self.buttons = held.buttons;
+            self.buttons_changed = true;
+        }
+        if held.moving == 0 && held.wheel == 0 {
+            self.held_ms = 0;
+        }
+        self.moving = held.moving;
+        self.wheel = held.wheel;
+    }
+
+    /// Called on every tick of the 1 kHz timer, returns a report when the
+    /// buttons changed or the cursor/wheel has to move
+    pub fn tick(&mut self) -> Option<WheelMouseReport> {
+        let mut report = WheelMouseReport {
+            buttons: self.buttons,
+            x: 0,
+            y: 0,
+            vertical_wheel: 0,
+            horizontal_wheel: 0,
+        };
+        let mut send = core::mem::take(&mut self.buttons_changed);
+
+        if self.moving == 0 && self.wheel == 0 {
+            return send.then_some(report);
+        }
+
+        // First step happens right away, the next ones every interval
+        if self.moving != 0 && self.held_ms.is_multiple_of(MOVE_INTERVAL) {
+            let speed = MOVE_MIN_SPEED
+                + (MOVE_MAX_SPEED - MOVE_MIN_SPEED) * self.held_ms.min(MOVE_TIME_TO_MAX)
+                    / MOVE_TIME_TO_MAX;
+            let (x, y) = direction_vector(self.moving);
+            report.x = x * speed as i8;
+            report.y = y * speed as i8;
+            send = true;
+        }
+
+        if self.wheel != 0 && self.held_ms.is_multiple_of(WHEEL_INTERVAL) {
+            let (x, y) = direction_vector(self.wheel);
+            report.horizontal_wheel = x;
+            // Positive wheel values scroll up
+            report.vertical_wheel = -y;
+            send = true;
+        }
+
+        self.held_ms = self.held_ms.saturating_add(1);
+        send.then_some(report)
+    }
+}
+
+impl Default for MouseKeys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Unit vector of the held directions, opposite directions cancel out
+fn direction_vector(directions: u8) -> (i8, i8) {
+    let held = |direction: MouseDirection| (directions & direction.bit() != 0) as i8;
+    (
+        held(MouseDirection::Right) - held(MouseDirection::Left),
+        held(MouseDirection::Down) - held(MouseDirection::Up),
+    )
+}
@@ -0,0 +1,24 @@
+//! Report of the Generic Desktop System Control device (power down, sleep,
+//! wake up), the device itself is part of the firmware
+
+/// Report values, offsets from the System Power Down usage
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum SystemControl {
+    #[default]
+    None = 0,
+    PowerDown = 1,
+    Sleep = 2,
+    WakeUp = 3,
+}
+
+impl SystemControl {
+    pub fn from_code(code: u16) -> Option<Self> {
+        Some(match code {
+            1 => SystemControl::PowerDown,
+            2 => SystemControl::Sleep,
+            3 => SystemControl::WakeUp,
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,83 @@
+use keyboard_core::fixed_vec::{CapacityError, FixedVec};
+
+fn filled(values: &[u8]) -> FixedVec<u8, 4> {
+    let mut vec = FixedVec::new(0);
+    for &value in values {
+        vec.try_push(value).unwrap();
+    }
+    vec
+}
+
+#[test]
+fn push_until_full() {
+    let mut vec = filled(&[1, 2, 3, 4]);
+    assert_eq!(vec.try_push(5), Err(CapacityError(5)));
+    assert_eq!(&*vec, [1, 2, 3, 4]);
+}
+
+#[test]
+fn pop_in_reverse_order() {
+    let mut vec = filled(&[1, 2]);
+    assert_eq!(vec.pop(), Some(2));
+    assert_eq!(vec.pop(), Some(1));
+    assert_eq!(vec.pop(), None);
+    assert!(vec.is_empty());
+}
+
+#[test]
+fn clear_and_truncate() {
+    let mut vec = filled(&[1, 2, 3]);
+    vec.truncate(5);
+    assert_eq!(&*vec, [1, 2, 3]);
+    vec.truncate(1);
+    assert_eq!(&*vec, [1]);
+    vec.clear();
+    assert!(vec.is_empty());
+    // The storage is reused
+    vec.try_push(7).unwrap();
+    assert_eq!(&*vec, [7]);
+}
+
+#[test]
+fn push_unique_skips_present_values() {
+    let mut vec = filled(&[1, 2, 3]);
+    assert_eq!(vec.push_unique(2), Ok(()));
+    assert_eq!(vec.push_unique(4), Ok(()));
+    // Present values are accepted even when full
+    assert_eq!(vec.push_unique(1), Ok(()));
+    assert_eq!(vec.push_unique(5), Err(CapacityError(5)));
+    assert_eq!(&*vec, [1, 2, 3, 4]);
+}
+
+#[test]
+fn retain_keeps_the_order() {
+    let mut vec = filled(&[1, 2, 3, 4]);
+    vec.retain(|&value| value % 2 == 0);
+    assert_eq!(&*vec, [2, 4]);
+    vec.retain(|_| false);
+    assert!(vec.is_empty());
+}
+
+#[test]
+fn slice_access_covers_the_elements_only() {
+    let mut vec = filled(&[3, 1, 2]);
+    vec.sort_unstable();
+    assert_eq!(&*vec, [1, 2, 3]);
+    assert_eq!(vec.iter().copied().sum::<u8>(), 6);
+    assert_eq!((&vec).into_iter().count(), 3);
+}
+
+#[test]
+fn dedup_removes_consecutive_repeats() {
+    let mut vec = filled(&[1, 1, 2, 1]);
+    vec.dedup();
+    assert_eq!(&*vec, [1, 2, 1]);
+
+    let mut vec = filled(&[3, 3, 3, 3]);
+    vec.dedup();
+    assert_eq!(&*vec, [3]);
+
+    let mut vec = filled(&[]);
+    vec.dedup();
+    assert!(vec.is_empty());
+}
@@ -0,0 +1,52 @@
+//! The engine state is global, this file holds the only test touching it
+
+use keyboard_core::layouts_def::{self, MultiKey};
+use shared_src::boot::crc32;
+use shared_src::config_protocol::{KeyKind, KeyPosition, RawKey};
+use shared_src::keymap_blob;
+use usbd_human_interface_device::page::Keyboard;
+
+fn position(side: u8, layer: u8, index: u8) -> KeyPosition {
+    KeyPosition { side, layer, index }
+}
+
+/// Format 1 blob with one left layer of three keys: A, a key kind unknown to
+/// this firmware and B
+fn blob() -> Vec<u8> {
+    let mut blob = vec![0x4D, 0x4B, 0x01, 0x01, 0x00, 0x03];
+    blob.extend_from_slice(&[0x00, 0x04, 0x00]);
+    blob.extend_from_slice(&[0x7F, 0x01, 0x00]);
+    blob.extend_from_slice(&[0x00, 0x05, 0x00]);
+    let crc = crc32(&blob);
+    blob.extend_from_slice(&crc.to_le_bytes());
+    blob
+}
+
+#[test]
+fn stored_keymap_loads_over_the_defaults() {
+    let default = layouts_def::get_key(position(0, 0, 1)).ok().unwrap();
+    let untouched = [position(0, 0, 3), position(0, 1, 0), position(1, 0, 0)]
+        .map(|position| layouts_def::get_key(position).ok().unwrap());
+
+    let blob = blob();
+    let (shape, entries) = keymap_blob::decode(&blob).unwrap();
+    layouts_def::load_keymap(shape, entries);
+
+    let key = |position| layouts_def::get_key(position).ok().unwrap();
+    assert!(key(position(0, 0, 0)) == MultiKey::KeyboardKey(Keyboard::A));
+    // The unknown kind keeps the default key
+    assert!(key(position(0, 0, 1)) == default);
+    assert!(key(position(0, 0, 2)) == MultiKey::KeyboardKey(Keyboard::B));
+    for (position, before) in [position(0, 0, 3), position(0, 1, 0), position(1, 0, 0)]
+        .into_iter()
+        .zip(untouched)
+    {
+        assert!(key(position) == before);
+    }
+    assert_eq!(
+        key(position(0, 0, 0)).to_raw(),
+        RawKey::new(KeyKind::Keyboard, 0x04)
+    );
+    // Not an edit to store again
+    assert!(!layouts_def::take_keymap_changed());
+}
@@ -0,0 +1,132 @@
+//! Configuration channel of a running keyboard through Linux hidraw, see
+//! `shared_src::config_protocol`.
+
+use keymap_tool::{layer_context, Error, Keymap, Side};
+use shared_src::config_protocol::{
+    KeyPosition, Report, Request, Response, PROTOCOL_VERSION, REPORT_LEN,
+};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+/// Start of the report descriptor of the raw HID interface: usage page 0xFF60,
+/// usage 0x61. QMK boards share it, they are told apart by [`Device::find`]
+const RAW_HID_DESCRIPTOR: [u8; 5] = [0x06, 0x60, 0xFF, 0x09, 0x61];
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+pub struct Device {
+    pub path: PathBuf,
+    file: File,
+    /// Input reports, read by a thread of their own so that a silent device
+    /// times out instead of blocking
+    reports: Receiver<Report>,
+}
+
+impl Device {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let error = |error: std::io::Error| Error(format!("{}: {error}", path.display()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(error)?;
+        let mut reader = file.try_clone().map_err(error)?;
+
+        let (sender, reports) = mpsc::channel();
+        thread::spawn(move || {
+            let mut report = [0; REPORT_LEN];
+            while let Ok(len) = reader.read(&mut report) {
+                if len == REPORT_LEN && sender.send(report).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            reports,
+        })
+    }
+
+    /// First raw HID interface that answers with our protocol version
+    pub fn find() -> Result<Self, Error> {
+        let mut names: Vec<_> = fs::read_dir("/sys/class/hidraw")
+            .map_err(|error| Error(format!("/sys/class/hidraw: {error}")))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name())
+            .collect();
+        names.sort();
+
+        for name in names {
+            let sys = Path::new("/sys/class/hidraw").join(&name);
+            let descriptor = fs::read(sys.join("device/report_descriptor")).unwrap_or_default();
+            if !descriptor.starts_with(&RAW_HID_DESCRIPTOR) {
+                continue;
+            }
+            let Ok(mut device) = Device::open(&Path::new("/dev").join(&name)) else {
+                continue;
+            };
+            if let Ok(Response::Version { protocol, .. }) = device.request(Request::GetVersion) {
+                if protocol == PROTOCOL_VERSION {
+                    return Ok(device);
+                }
+            }
+        }
+        Err(Error(String::from(
+            "no keyboard found, check the permissions of /dev/hidraw*",
+        )))
+    }
+
+    pub fn request(&mut self, request: Request) -> Result<Response, Error> {
+        let mut report = [0; REPORT_LEN];
+        request.write(&mut report);
+        // hidraw takes the report id first, 0 as the interface has none
+        let mut data = vec![0];
+        data.extend_from_slice(&report);
+        self.file
+            .write_all(&data)
+            .map_err(|error| Error(format!("{}: {error}", self.path.display())))?;
+
+        let command = request.command();
+        loop {
+            let report = self
+                .reports
+                .recv_timeout(TIMEOUT)
+                .map_err(|_| Error(format!("{}: no response", self.path.display())))?;
+            // Leftovers of an earlier request are skipped
+            if report[0] == command as u8 {
+                return Response::parse(command, &report)
+                    .map_err(|status| Error(format!("malformed response: {status:?}")));
+            }
+        }
+    }
+}
+
+/// Writes every matrix position of every layer, returns how many
+pub fn push(device: &mut Device, keymap: &Keymap) -> Result<usize, Error> {
+    let mut entries = 0;
+    for side in Side::ALL {
+        for (layer, keys) in keymap.layers(side).iter().enumerate() {
+            for (index, &key) in keys.matrix(side).iter().enumerate() {
+                let position = KeyPosition {
+                    side: side.index(),
+                    layer: layer as u8,
+                    index: index as u8,
+                };
+                match device.request(Request::SetKeymapEntry(position, key))? {
+                    Response::Done => entries += 1,
+                    response => {
+                        return Err(Error(format!(
+                            "{}: position {index} refused: {response:?}",
+                            layer_context(side, layer, &keys.name)
+                        )))
+                    }
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
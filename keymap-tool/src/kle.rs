@@ -0,0 +1,243 @@
+//! keyboard-layout-editor.com layout of the keymap, as its JSON download.
+//! Every key lists its layers in its legends, layer `i` in legend `i` of the
+//! default alignment, spelled as in the keymap file. The notes hold the layer
+//! names, one `<side> <index>: <name>` per line.
+//!
+//! On import the keys are read row by row, in each row the left half first,
+//! as [`export`] writes them. Without notes a half gets as many layers as its
+//! keys have legends.
+
+use keymap_tool::{layer_context, token, Error, Keymap, Layer, Side, NO_EVENT, ROW_LENGTHS};
+use serde_json::{json, Value};
+use shared_src::keycodes;
+
+const NAME: &str = "VirhPotujnosti";
+/// Legend alignment whose legends are numbered in order, the default one
+const ALIGNMENT: u64 = 4;
+
+pub fn export(keymap: &Keymap) -> String {
+    let notes: Vec<String> = Side::ALL
+        .into_iter()
+        .flat_map(|side| {
+            keymap
+                .layers(side)
+                .iter()
+                .enumerate()
+                .map(move |(i, layer)| format!("{} {i}: {}", side.name(), layer.name))
+        })
+        .collect();
+
+    let mut lines = vec![json!({ "name": NAME, "notes": notes.join("\n") }).to_string()];
+    for (r, len) in ROW_LENGTHS.into_iter().enumerate() {
+        let mut row = Vec::new();
+        for side in Side::ALL {
+            // The right half starts a key away from the left one
+            let offset = match side {
+                Side::Left => side.columns(r).start,
+                Side::Right => 1,
+            };
+            if offset > 0 {
+                row.push(json!({ "x": offset }));
+            }
+
+            for k in 0..len {
+                let mut legends: Vec<String> = keymap
+                    .layers(side)
+                    .iter()
+                    .map(|layer| match layer.rows()[r][k] {
+                        NO_EVENT => String::new(),
+                        key => token(key),
+                    })
+                    .collect();
+                while legends.last().is_some_and(String::is_empty) {
+                    legends.pop();
+                }
+                row.push(Value::from(legends.join("\n")));
+            }
+        }
+        lines.push(Value::from(row).to_string());
+    }
+    format!("[\n{}\n]\n", lines.join(",\n"))
+}
+
+pub fn import(text: &str) -> Result<Keymap, Error> {
+    let doc: Value = serde_json::from_str(text).map_err(|error| Error(error.to_string()))?;
+    let items = doc
+        .as_array()
+        .ok_or_else(|| Error(String::from("expected the JSON array of a layout")))?;
+
+    let mut names: [Vec<String>; 2] = Default::default();
+    let mut rows: Vec<Vec<&str>> = Vec::new();
+    for item in items {
+        match item {
+            Value::Object(metadata) => {
+                let notes = metadata.get("notes").and_then(Value::as_str);
+                for line in notes.unwrap_or("").lines() {
+                    if let Some((side, index, name)) = parse_note(line) {
+                        if index == names[side].len() {
+                            names[side].push(name.to_string());
+                        }
+                    }
+                }
+            }
+            Value::Array(entries) => {
+                let mut keys = Vec::new();
+                for entry in entries {
+                    match entry {
+                        Value::String(legends) => keys.push(legends.as_str()),
+                        Value::Object(properties) => {
+                            let alignment = properties.get("a").and_then(Value::as_u64);
+                            if alignment.is_some_and(|a| a != ALIGNMENT) {
+                                return Err(Error(format!(
+                                    "row {}: only the default legend alignment is supported",
+                                    rows.len() + 1
+                                )));
+                            }
+                        }
+                        _ => {
+                            return Err(Error(format!(
+                                "row {}: unexpected {entry}",
+                                rows.len() + 1
+                            )))
+                        }
+                    }
+                }
+                rows.push(keys);
+            }
+            _ => return Err(Error(format!("unexpected {item}"))),
+        }
+    }
+
+    if rows.len() != ROW_LENGTHS.len() {
+        return Err(Error(format!(
+            "expected {} rows of keys, found {}",
+            ROW_LENGTHS.len(),
+            rows.len()
+        )));
+    }
+    // Legends of every key of each half, in the order of the keymap file
+    let mut legends: [Vec<Vec<&str>>; 2] = Default::default();
+    for (r, (row, len)) in rows.iter().zip(ROW_LENGTHS).enumerate() {
+        if row.len() != 2 * len {
+            return Err(Error(format!(
+                "row {}: expected {} keys, found {}",
+                r + 1,
+                2 * len,
+                row.len()
+            )));
+        }
+        legends[0].extend(
+            row[..len]
+                .iter()
+                .map(|key| key.split('\n').collect::<Vec<_>>()),
+        );
+        legends[1].extend(
+            row[len..]
+                .iter()
+                .map(|key| key.split('\n').collect::<Vec<_>>()),
+        );
+    }
+
+    let mut keymap = Keymap::default();
+    for (s, side) in Side::ALL.into_iter().enumerate() {
+        let layer_count = match names[s].len() {
+            0 => legends[s].iter().map(Vec::len).max().unwrap_or(1),
+            count => count,
+        };
+        for i in 0..layer_count {
+            let name = names[s]
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| format!("Layer {i}"));
+            let keys = legends[s]
+                .iter()
+                .enumerate()
+                .map(|(k, key)| match key.get(i).copied().unwrap_or("") {
+                    "" => Ok(NO_EVENT),
+                    legend => keycodes::parse(legend).map_err(|error| {
+                        Error(format!(
+                            "{}, key {}: {error} `{legend}`",
+                            layer_context(side, i, &name),
+                            k + 1
+                        ))
+                    }),
+                })
+                .collect::<Result<_, _>>()?;
+            keymap.layers_mut(side).push(Layer { name, keys });
+        }
+    }
+    Ok(keymap)
+}
+
+/// Side, index and name of a `left 0: Base` line of the notes
+fn parse_note(line: &str) -> Option<(usize, usize, &str)> {
+    let (layer, name) = line.split_once(": ")?;
+    let (side, index) = layer.split_once(' ')?;
+    let side = Side::ALL.iter().position(|s| s.name() == side)?;
+    Some((side, index.parse().ok()?, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keymap_tool::KEYS_PER_SIDE;
+
+    /// One layer a half, the first key of each row bound
+    fn known_keymap() -> Keymap {
+        let layer = |name: &str, keys: [&str; 5]| {
+            let mut layer = Layer {
+                name: name.to_string(),
+                keys: vec![NO_EVENT; KEYS_PER_SIDE],
+            };
+            let mut first = 0;
+            for (len, key) in ROW_LENGTHS.into_iter().zip(keys) {
+                layer.keys[first] = keycodes::parse(key).unwrap();
+                first += len;
+            }
+            layer
+        };
+        Keymap {
+            left: vec![layer("Base", ["Esc", "Tab", "Caps", "LSft", "LAlt"])],
+            right: vec![
+                layer("Base", ["6", "Y", "H", "N", "RAlt"]),
+                layer("Fn", ["F6", "Home", "Left", "End", "consumer(PlayPause)"]),
+            ],
+        }
+    }
+
+    #[test]
+    fn export_of_a_known_layout() {
+        let expected = r#"[
+{"name":"VirhPotujnosti","notes":"left 0: Base\nright 0: Base\nright 1: Fn"},
+["Esc","","","","","",{"x":1},"6\nF6","","","","",""],
+["Tab","","","","","",{"x":1},"Y\nHome","","","","",""],
+["Caps","","","","","",{"x":1},"H\nLeft","","","","",""],
+["LSft","","","","","",{"x":1},"N\nEnd","","","","",""],
+[{"x":1},"LAlt","","","","",{"x":1},"RAlt\nconsumer(PlayPause)","","","",""]
+]
+"#;
+        assert_eq!(export(&known_keymap()), expected);
+    }
+
+    #[test]
+    fn round_trip_is_exact() {
+        let keymap = known_keymap();
+        assert_eq!(import(&export(&keymap)).unwrap(), keymap);
+    }
+
+    #[test]
+    fn layers_without_notes_come_from_the_legends() {
+        let mut text = export(&known_keymap());
+        text = text.replacen(
+            r#""notes":"left 0: Base\nright 0: Base\nright 1: Fn""#,
+            r#""notes":"""#,
+            1,
+        );
+
+        let keymap = import(&text).unwrap();
+        assert_eq!(keymap.left.len(), 1);
+        assert_eq!(keymap.right.len(), 2);
+        assert_eq!(keymap.right[1].name, "Layer 1");
+        assert_eq!(keymap.right[1].keys, known_keymap().right[1].keys);
+    }
+}
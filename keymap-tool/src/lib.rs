@@ -0,0 +1,385 @@
+//! Keymap file of the keyboard (`keyboard-core/keymap.toml`, the format is
+//! described there): reading and writing it, the table the firmware build
+//! compiles it into and drawings of its layers.
+//!
+//! Keys are held as [`RawKey`] and named by [`shared_src::keycodes`], the
+//! firmware decodes the same values.
+
+use shared_src::config_protocol::{KeyKind, RawKey};
+use shared_src::keycodes::{self, NO_KEY};
+use std::fmt::{self, Write};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use toml::{Table, Value};
+
+/// Keys in each physical row of a half, top to bottom
+pub const ROW_LENGTHS: [usize; 5] = [6, 6, 6, 6, 5];
+/// Keys of a half
+pub const KEYS_PER_SIDE: usize = 29;
+/// Positions in the matrix of a half, one of them holds no key
+pub const MATRIX_LEN: usize = 30;
+pub const MATRIX_COLUMNS: usize = 6;
+
+pub const NO_EVENT: RawKey = RawKey::new(KeyKind::Keyboard, 0);
+
+#[derive(Debug)]
+pub struct Error(pub String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Self(message)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    pub const ALL: [Side; 2] = [Side::Left, Side::Right];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Side::Left => "left",
+            Side::Right => "right",
+        }
+    }
+
+    /// `KeyPosition::side` of the half
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// Matrix columns of the keys of a row. The thumb rows sit on the inner
+    /// side, the last five columns on the left and the first five on the
+    /// right
+    pub fn columns(self, row: usize) -> Range<usize> {
+        let first = match self {
+            Side::Left if row == ROW_LENGTHS.len() - 1 => 1,
+            _ => 0,
+        };
+        first..first + ROW_LENGTHS[row]
+    }
+
+    /// Matrix position of every key, in the order of the keymap file
+    pub fn matrix_positions(self) -> impl Iterator<Item = usize> {
+        (0..ROW_LENGTHS.len()).flat_map(move |row| {
+            self.columns(row)
+                .map(move |column| row * MATRIX_COLUMNS + column)
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Layer {
+    pub name: String,
+    /// [`KEYS_PER_SIDE`] keys, row by row
+    pub keys: Vec<RawKey>,
+}
+
+impl Layer {
+    pub fn rows(&self) -> Vec<&[RawKey]> {
+        split_rows(&self.keys)
+    }
+
+    /// Keys by matrix position, the positions without a key hold
+    /// `NoEventIndicated`
+    pub fn matrix(&self, side: Side) -> [RawKey; MATRIX_LEN] {
+        let mut matrix = [NO_EVENT; MATRIX_LEN];
+        for (position, &key) in side.matrix_positions().zip(&self.keys) {
+            matrix[position] = key;
+        }
+        matrix
+    }
+
+    /// Inverse of [`Layer::matrix`], fails if a position without a key holds
+    /// something
+    pub fn from_matrix(name: String, side: Side, matrix: &[RawKey]) -> Result<Self, Error> {
+        let keys: Vec<RawKey> = side.matrix_positions().map(|i| matrix[i]).collect();
+        let bound_without_key = (0..MATRIX_LEN)
+            .filter(|&i| !side.matrix_positions().any(|position| position == i))
+            .find(|&i| matrix[i] != NO_EVENT);
+        if let Some(i) = bound_without_key {
+            return Err(Error(format!(
+                "matrix position {i} has no key but is bound"
+            )));
+        }
+        Ok(Self { name, keys })
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Keymap {
+    pub left: Vec<Layer>,
+    pub right: Vec<Layer>,
+}
+
+impl Keymap {
+    pub fn layers(&self, side: Side) -> &[Layer] {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+
+    pub fn layers_mut(&mut self, side: Side) -> &mut Vec<Layer> {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| Error(format!("{}: {error}", path.display())))?;
+        Self::parse(&text).map_err(|error| Error(format!("{}: {error}", path.display())))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let doc: Table = text
+            .parse()
+            .map_err(|error: toml::de::Error| Error(error.to_string()))?;
+        Ok(Self {
+            left: read_layers(&doc, Side::Left)?,
+            right: read_layers(&doc, Side::Right)?,
+        })
+    }
+
+    /// The keymap file, keys aligned in columns
+    pub fn to_toml(&self) -> String {
+        let mut text = String::from("# Keymap file, see keyboard-core/keymap.toml for the format\n");
+        for side in Side::ALL {
+            for layer in self.layers(side) {
+                let tokens: Vec<String> = layer.keys.iter().map(|&key| token(key)).collect();
+                let rows = split_rows(&tokens);
+                let widths = column_widths(side, &rows, String::len);
+
+                writeln!(text, "\n[[{}]]", side.name()).unwrap();
+                writeln!(text, "name = {}", Value::String(layer.name.clone())).unwrap();
+                text += "keys = \"\"\"\n";
+                for (r, row) in rows.iter().enumerate() {
+                    let columns = side.columns(r);
+                    let mut line: String = widths[..columns.start]
+                        .iter()
+                        .map(|width| " ".repeat(width + 2))
+                        .collect();
+                    for (column, token) in columns.zip(*row) {
+                        line += &format!("{token:<width$}  ", width = widths[column]);
+                    }
+                    writeln!(text, "{}", line.trim_end()).unwrap();
+                }
+                text += "\"\"\"\n";
+            }
+        }
+        text
+    }
+
+    /// `LEFT_KEYMAP` and `RIGHT_KEYMAP` of `layouts_def`, with the diagrams
+    /// of their layers as documentation
+    pub fn rust_table(&self) -> String {
+        let mut code = String::from("// Generated from keymap.toml\n");
+        for side in Side::ALL {
+            let constant = side.name().to_uppercase();
+            writeln!(
+                code,
+                "\n/// Default {} layers\n///\n/// ```text",
+                side.name()
+            )
+            .unwrap();
+            for (i, layer) in self.layers(side).iter().enumerate() {
+                for line in draw_layer(side, i, layer).lines() {
+                    writeln!(code, "/// {line}").unwrap();
+                }
+                writeln!(code, "///").unwrap();
+            }
+            writeln!(code, "/// ```\n#[rustfmt::skip]").unwrap();
+            writeln!(
+                code,
+                "const {constant}_KEYMAP: [KeybardMatrixLayout; {constant}_LAYERS] = ["
+            )
+            .unwrap();
+            for layer in self.layers(side) {
+                writeln!(code, "    // {}\n    [", layer.name).unwrap();
+                for row in layer.matrix(side).chunks(MATRIX_COLUMNS) {
+                    let keys: Vec<String> = row.iter().map(|&key| macro_call(key)).collect();
+                    writeln!(code, "        {},", keys.join(", ")).unwrap();
+                }
+                writeln!(code, "    ],").unwrap();
+            }
+            writeln!(code, "];").unwrap();
+        }
+        code
+    }
+
+    /// Diagrams of every layer
+    pub fn draw(&self) -> String {
+        let mut text = String::new();
+        for side in Side::ALL {
+            for (i, layer) in self.layers(side).iter().enumerate() {
+                writeln!(text, "{}", draw_layer(side, i, layer)).unwrap();
+            }
+        }
+        text
+    }
+}
+
+/// How error messages name a layer
+pub fn layer_context(side: Side, index: usize, name: &str) -> String {
+    match name {
+        "" => format!("{} layer {index}", side.name()),
+        _ => format!("{} layer {index} ({name})", side.name()),
+    }
+}
+
+fn read_layers(doc: &Table, side: Side) -> Result<Vec<Layer>, Error> {
+    let layers = doc
+        .get(side.name())
+        .and_then(Value::as_array)
+        .ok_or_else(|| Error(format!("no [[{}]] layers", side.name())))?;
+
+    let mut result = Vec::new();
+    for (i, layer) in layers.iter().enumerate() {
+        let name = layer.get("name").and_then(Value::as_str).unwrap_or("");
+        let context = layer_context(side, i, name);
+        let text = layer
+            .get("keys")
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error(format!("{context}: `keys` is missing")))?;
+
+        let rows: Vec<Vec<&str>> = text
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .filter(|row| !row.is_empty())
+            .collect();
+        if rows.len() != ROW_LENGTHS.len() {
+            return Err(Error(format!(
+                "{context}: expected {} rows of keys, found {}",
+                ROW_LENGTHS.len(),
+                rows.len()
+            )));
+        }
+
+        let mut keys = Vec::with_capacity(KEYS_PER_SIDE);
+        for (r, (row, len)) in rows.iter().zip(ROW_LENGTHS).enumerate() {
+            if row.len() != len {
+                return Err(Error(format!(
+                    "{context}, row {}: expected {len} keys, found {}",
+                    r + 1,
+                    row.len()
+                )));
+            }
+            for token in row {
+                let key = keycodes::parse(token).map_err(|error| {
+                    Error(format!("{context}, row {}: {error} `{token}`", r + 1))
+                })?;
+                keys.push(key);
+            }
+        }
+        result.push(Layer {
+            name: name.to_string(),
+            keys,
+        });
+    }
+    Ok(result)
+}
+
+/// Keymap file spelling of a key, `___` for keys without a name
+pub fn token(key: RawKey) -> String {
+    keycodes::name(key).map_or_else(|| NO_KEY.to_string(), |name| name.to_string())
+}
+
+/// Text of a key in the diagrams, the kind of key is left out where the name
+/// is clear enough
+pub fn label(key: RawKey) -> String {
+    let Some(name) = keycodes::name(key).filter(|_| key != NO_EVENT) else {
+        return String::new();
+    };
+    match name.kind {
+        KeyKind::Keyboard => name.to_string(),
+        KeyKind::MouseMove => format!("Ms {}", name.variant),
+        KeyKind::MouseWheel => format!("Wh {}", name.variant),
+        KeyKind::MouseButton => format!("Btn {}", name.variant),
+        _ => name.variant.to_string(),
+    }
+}
+
+fn macro_call(key: RawKey) -> String {
+    let name = keycodes::name(key).map_or("NoEventIndicated", |name| name.variant);
+    format!("{}!({name})", keycodes::kind_name(key.kind))
+}
+
+fn split_rows<T>(keys: &[T]) -> Vec<&[T]> {
+    ROW_LENGTHS
+        .iter()
+        .scan(0, |start, &len| {
+            *start += len;
+            Some(&keys[*start - len..*start])
+        })
+        .collect()
+}
+
+/// Widest text of every matrix column, at least 3
+fn column_widths<T>(side: Side, rows: &[&[T]], width: impl Fn(&T) -> usize) -> [usize; 6] {
+    let mut widths = [3; MATRIX_COLUMNS];
+    for (r, row) in rows.iter().enumerate() {
+        for (column, item) in side.columns(r).zip(row.iter()) {
+            widths[column] = widths[column].max(width(item));
+        }
+    }
+    widths
+}
+
+/// Boxes of a layer as the keys sit on the half, every column as wide as its
+/// longest label
+pub fn draw_layer(side: Side, index: usize, layer: &Layer) -> String {
+    let labels: Vec<String> = layer.keys.iter().map(|&key| label(key)).collect();
+    let rows = split_rows(&labels);
+    let widths = column_widths(side, &rows, String::len);
+
+    let indent = |columns: &Range<usize>| -> String {
+        widths[..columns.start]
+            .iter()
+            .map(|width| " ".repeat(width + 3))
+            .collect()
+    };
+    let border = |columns: Range<usize>| -> String {
+        let mut line = indent(&columns) + "+";
+        for width in &widths[columns] {
+            line += &"-".repeat(width + 2);
+            line += "+";
+        }
+        line
+    };
+
+    let mut text = format!("{}:\n", layer_context(side, index, &layer.name));
+    for (r, row) in rows.iter().enumerate() {
+        // Shared with the row above, spans the keys of both
+        let columns = side.columns(r);
+        let above = r
+            .checked_sub(1)
+            .map_or(columns.clone(), |above| side.columns(above));
+        text += &border(above.start.min(columns.start)..above.end.max(columns.end));
+        text += "\n";
+
+        let mut line = indent(&columns) + "|";
+        for (column, label) in columns.zip(row.iter()) {
+            line += &format!(" {label:<width$} |", width = widths[column]);
+        }
+        text += &line;
+        text += "\n";
+    }
+    text += &border(side.columns(ROW_LENGTHS.len() - 1));
+    text += "\n";
+    text
+}
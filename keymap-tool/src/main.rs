@@ -0,0 +1,87 @@
+//! Command-line tool for the keymap file: checks and draws it, converts it to
+//! and from QMK and keyboard-layout-editor files and writes it to a running
+//! keyboard. Results go to stdout.
+
+mod device;
+mod kle;
+mod qmk;
+mod svg;
+
+use keymap_tool::{Error, Keymap};
+use std::path::Path;
+use std::{env, fs, process};
+
+const USAGE: &str = "\
+usage: keymap-tool <command> <file> [device]
+
+commands:
+  validate <keymap.toml>          check the keymap file
+  render <keymap.toml>            draw the layers as text
+  svg <keymap.toml>               draw the layers as SVG
+  to-qmk <keymap.toml>            print it as a QMK keymap.json
+  from-qmk <keymap.json>          print the keymap file of a QMK keymap.json
+  to-kle <keymap.toml>            print a keyboard-layout-editor layout, the
+                                  legend of a key lists its layers
+  from-kle <layout.json>          print the keymap file of such a layout
+  push <keymap.toml> [/dev/hidrawN]
+                                  write the keymap to the keyboard through the
+                                  configuration channel, found by itself when
+                                  no device is given";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (command, file, device) = match args.as_slice() {
+        [command, file] => (command.as_str(), Path::new(file), None),
+        [command, file, device] if command == "push" => {
+            (command.as_str(), Path::new(file), Some(Path::new(device)))
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            process::exit(2);
+        }
+    };
+
+    if let Err(error) = run(command, file, device) {
+        eprintln!("error: {error}");
+        process::exit(1);
+    }
+}
+
+fn run(command: &str, file: &Path, device: Option<&Path>) -> Result<(), Error> {
+    match command {
+        "validate" => {
+            let keymap = Keymap::load(file)?;
+            println!(
+                "{}: {} left and {} right layers",
+                file.display(),
+                keymap.left.len(),
+                keymap.right.len()
+            );
+        }
+        "render" => print!("{}", Keymap::load(file)?.draw()),
+        "svg" => print!("{}", svg::draw(&Keymap::load(file)?)),
+        "to-qmk" => print!("{}", qmk::export(&Keymap::load(file)?)),
+        "from-qmk" => print!("{}", qmk::import(&read(file)?)?.to_toml()),
+        "to-kle" => print!("{}", kle::export(&Keymap::load(file)?)),
+        "from-kle" => print!("{}", kle::import(&read(file)?)?.to_toml()),
+        "push" => {
+            let keymap = Keymap::load(file)?;
+            let mut device = match device {
+                Some(path) => device::Device::open(path)?,
+                None => device::Device::find()?,
+            };
+            let entries = device::push(&mut device, &keymap)?;
+            println!(
+                "{entries} entries written to {}, the keyboard stores them once \
+                 the edits stop",
+                device.path.display()
+            );
+        }
+        _ => return Err(Error(format!("unknown command `{command}`\n{USAGE}"))),
+    }
+    Ok(())
+}
+
+fn read(path: &Path) -> Result<String, Error> {
+    fs::read_to_string(path).map_err(|error| Error(format!("{}: {error}", path.display())))
+}
@@ -0,0 +1,257 @@
+//! QMK `keymap.json` of the keymap, in the 10x6 matrix VIA sees (see
+//! `left-stm32f1/src/via.rs`): rows 0-4 the left half, 5-9 the right.
+//! Keycodes are mapped by `shared_src::via` as for VIA, a key without a QMK
+//! keycode exports as `KC_NO`.
+//!
+//! QMK layers span both halves. The layers the left half doesn't have read
+//! `KC_NO`, and on import the trailing layers left empty on a half are
+//! dropped from it.
+
+use keymap_tool::{
+    layer_context, token, Error, Keymap, Layer, Side, MATRIX_COLUMNS, MATRIX_LEN, NO_EVENT,
+};
+use serde_json::Value;
+use shared_src::config_protocol::RawKey;
+use shared_src::{keycodes, via};
+use std::fmt::Write;
+
+const KEYBOARD: &str = "virhpotujnosti";
+const LAYOUT: &str = "LAYOUT";
+
+/// Names of the keycodes `shared_src::via` maps, the first one of a keycode
+/// is the one exported
+#[rustfmt::skip]
+const KEYCODES: &[(&str, u16)] = &[
+    ("KC_NO", 0x0000), ("KC_A", 0x0004), ("KC_B", 0x0005), ("KC_C", 0x0006), ("KC_D", 0x0007),
+    ("KC_E", 0x0008), ("KC_F", 0x0009), ("KC_G", 0x000A), ("KC_H", 0x000B), ("KC_I", 0x000C),
+    ("KC_J", 0x000D), ("KC_K", 0x000E), ("KC_L", 0x000F), ("KC_M", 0x0010), ("KC_N", 0x0011),
+    ("KC_O", 0x0012), ("KC_P", 0x0013), ("KC_Q", 0x0014), ("KC_R", 0x0015), ("KC_S", 0x0016),
+    ("KC_T", 0x0017), ("KC_U", 0x0018), ("KC_V", 0x0019), ("KC_W", 0x001A), ("KC_X", 0x001B),
+    ("KC_Y", 0x001C), ("KC_Z", 0x001D), ("KC_1", 0x001E), ("KC_2", 0x001F), ("KC_3", 0x0020),
+    ("KC_4", 0x0021), ("KC_5", 0x0022), ("KC_6", 0x0023), ("KC_7", 0x0024), ("KC_8", 0x0025),
+    ("KC_9", 0x0026), ("KC_0", 0x0027), ("KC_ENT", 0x0028), ("KC_ESC", 0x0029),
+    ("KC_BSPC", 0x002A), ("KC_TAB", 0x002B), ("KC_SPC", 0x002C), ("KC_MINS", 0x002D),
+    ("KC_EQL", 0x002E), ("KC_LBRC", 0x002F), ("KC_RBRC", 0x0030), ("KC_BSLS", 0x0031),
+    ("KC_NUHS", 0x0032), ("KC_SCLN", 0x0033), ("KC_QUOT", 0x0034), ("KC_GRV", 0x0035),
+    ("KC_COMM", 0x0036), ("KC_DOT", 0x0037), ("KC_SLSH", 0x0038), ("KC_CAPS", 0x0039),
+    ("KC_F1", 0x003A), ("KC_F2", 0x003B), ("KC_F3", 0x003C), ("KC_F4", 0x003D),
+    ("KC_F5", 0x003E), ("KC_F6", 0x003F), ("KC_F7", 0x0040), ("KC_F8", 0x0041),
+    ("KC_F9", 0x0042), ("KC_F10", 0x0043), ("KC_F11", 0x0044), ("KC_F12", 0x0045),
+    ("KC_PSCR", 0x0046), ("KC_SCRL", 0x0047), ("KC_PAUS", 0x0048), ("KC_INS", 0x0049),
+    ("KC_HOME", 0x004A), ("KC_PGUP", 0x004B), ("KC_DEL", 0x004C), ("KC_END", 0x004D),
+    ("KC_PGDN", 0x004E), ("KC_RGHT", 0x004F), ("KC_LEFT", 0x0050), ("KC_DOWN", 0x0051),
+    ("KC_UP", 0x0052), ("KC_NUM", 0x0053), ("KC_PSLS", 0x0054), ("KC_PAST", 0x0055),
+    ("KC_PMNS", 0x0056), ("KC_PPLS", 0x0057), ("KC_PENT", 0x0058), ("KC_P1", 0x0059),
+    ("KC_P2", 0x005A), ("KC_P3", 0x005B), ("KC_P4", 0x005C), ("KC_P5", 0x005D),
+    ("KC_P6", 0x005E), ("KC_P7", 0x005F), ("KC_P8", 0x0060), ("KC_P9", 0x0061),
+    ("KC_P0", 0x0062), ("KC_PDOT", 0x0063), ("KC_NUBS", 0x0064), ("KC_APP", 0x0065),
+    ("KC_KB_POWER", 0x0066), ("KC_PEQL", 0x0067), ("KC_F13", 0x0068), ("KC_F14", 0x0069),
+    ("KC_F15", 0x006A), ("KC_F16", 0x006B), ("KC_F17", 0x006C), ("KC_F18", 0x006D),
+    ("KC_F19", 0x006E), ("KC_F20", 0x006F), ("KC_F21", 0x0070), ("KC_F22", 0x0071),
+    ("KC_F23", 0x0072), ("KC_F24", 0x0073), ("KC_EXEC", 0x0074), ("KC_HELP", 0x0075),
+    ("KC_MENU", 0x0076), ("KC_SLCT", 0x0077), ("KC_STOP", 0x0078), ("KC_AGIN", 0x0079),
+    ("KC_UNDO", 0x007A), ("KC_CUT", 0x007B), ("KC_COPY", 0x007C), ("KC_PSTE", 0x007D),
+    ("KC_FIND", 0x007E), ("KC_KB_MUTE", 0x007F), ("KC_KB_VOLUME_UP", 0x0080),
+    ("KC_KB_VOLUME_DOWN", 0x0081), ("KC_LCAP", 0x0082), ("KC_LNUM", 0x0083),
+    ("KC_LSCR", 0x0084), ("KC_PCMM", 0x0085), ("KC_KP_EQUAL_AS400", 0x0086),
+    ("KC_INT1", 0x0087), ("KC_INT2", 0x0088), ("KC_INT3", 0x0089), ("KC_INT4", 0x008A),
+    ("KC_INT5", 0x008B), ("KC_INT6", 0x008C), ("KC_INT7", 0x008D), ("KC_INT8", 0x008E),
+    ("KC_INT9", 0x008F), ("KC_LNG1", 0x0090), ("KC_LNG2", 0x0091), ("KC_LNG3", 0x0092),
+    ("KC_LNG4", 0x0093), ("KC_LNG5", 0x0094), ("KC_LNG6", 0x0095), ("KC_LNG7", 0x0096),
+    ("KC_LNG8", 0x0097), ("KC_LNG9", 0x0098), ("KC_ERAS", 0x0099), ("KC_SYRQ", 0x009A),
+    ("KC_CNCL", 0x009B), ("KC_CLR", 0x009C), ("KC_PRIR", 0x009D), ("KC_RETN", 0x009E),
+    ("KC_SEPR", 0x009F), ("KC_OUT", 0x00A0), ("KC_OPER", 0x00A1), ("KC_CLAG", 0x00A2),
+    ("KC_CRSL", 0x00A3), ("KC_EXSL", 0x00A4), ("KC_PWR", 0x00A5), ("KC_SLEP", 0x00A6),
+    ("KC_WAKE", 0x00A7), ("KC_MUTE", 0x00A8), ("KC_VOLU", 0x00A9), ("KC_VOLD", 0x00AA),
+    ("KC_MNXT", 0x00AB), ("KC_MPRV", 0x00AC), ("KC_MSTP", 0x00AD), ("KC_MPLY", 0x00AE),
+    ("KC_MSEL", 0x00AF), ("KC_EJCT", 0x00B0), ("KC_MAIL", 0x00B1), ("KC_CALC", 0x00B2),
+    ("KC_MYCM", 0x00B3), ("KC_WSCH", 0x00B4), ("KC_WHOM", 0x00B5), ("KC_WBAK", 0x00B6),
+    ("KC_WFWD", 0x00B7), ("KC_WSTP", 0x00B8), ("KC_WREF", 0x00B9), ("KC_WFAV", 0x00BA),
+    ("KC_MFFD", 0x00BB), ("KC_MRWD", 0x00BC), ("KC_BRIU", 0x00BD), ("KC_BRID", 0x00BE),
+    ("KC_CPNL", 0x00BF), ("KC_ASST", 0x00C0), ("KC_MCTL", 0x00C1), ("KC_LPAD", 0x00C2),
+    ("KC_MS_U", 0x00CD), ("KC_MS_D", 0x00CE), ("KC_MS_L", 0x00CF), ("KC_MS_R", 0x00D0),
+    ("KC_BTN1", 0x00D1), ("KC_BTN2", 0x00D2), ("KC_BTN3", 0x00D3), ("KC_BTN4", 0x00D4),
+    ("KC_BTN5", 0x00D5), ("KC_WH_U", 0x00D9), ("KC_WH_D", 0x00DA), ("KC_WH_L", 0x00DB),
+    ("KC_WH_R", 0x00DC), ("KC_LCTL", 0x00E0), ("KC_LSFT", 0x00E1), ("KC_LALT", 0x00E2),
+    ("KC_LGUI", 0x00E3), ("KC_RCTL", 0x00E4), ("KC_RSFT", 0x00E5), ("KC_RALT", 0x00E6),
+    ("KC_RGUI", 0x00E7), ("QK_BOOT", 0x7C00), ("XXXXXXX", 0x0000), ("KC_ENTER", 0x0028),
+    ("KC_ESCAPE", 0x0029), ("KC_BACKSPACE", 0x002A), ("KC_SPACE", 0x002C),
+    ("KC_DELETE", 0x004C), ("KC_CAPS_LOCK", 0x0039), ("KC_NUM_LOCK", 0x0053), ("MS_UP", 0x00CD),
+    ("MS_DOWN", 0x00CE), ("MS_LEFT", 0x00CF), ("MS_RGHT", 0x00D0), ("MS_BTN1", 0x00D1),
+    ("MS_BTN2", 0x00D2), ("MS_BTN3", 0x00D3), ("MS_BTN4", 0x00D4), ("MS_BTN5", 0x00D5),
+    ("MS_WHLU", 0x00D9), ("MS_WHLD", 0x00DA), ("MS_WHLL", 0x00DB), ("MS_WHLR", 0x00DC),
+    ("QK_BOOTLOADER", 0x7C00),
+];
+
+/// Read as `KC_NO`, layers don't fall through in this firmware
+const TRANSPARENT: &[&str] = &["KC_TRNS", "KC_TRANSPARENT", "_______"];
+
+pub fn export(keymap: &Keymap) -> String {
+    let layer_count = keymap.left.len().max(keymap.right.len());
+    let mut layers = Vec::new();
+    for layer in 0..layer_count {
+        let mut rows = Vec::new();
+        for side in Side::ALL {
+            let matrix = keymap
+                .layers(side)
+                .get(layer)
+                .map_or([NO_EVENT; MATRIX_LEN], |keys| keys.matrix(side));
+            for row in matrix.chunks(MATRIX_COLUMNS) {
+                let names: Vec<String> = row
+                    .iter()
+                    .map(|&key| Value::from(keycode_name(side, layer, key)).to_string())
+                    .collect();
+                rows.push(format!("      {}", names.join(", ")));
+            }
+        }
+        layers.push(format!("    [\n{}\n    ]", rows.join(",\n")));
+    }
+
+    let mut text = String::from("{\n");
+    writeln!(text, "  \"version\": 1,").unwrap();
+    writeln!(text, "  \"keyboard\": \"{KEYBOARD}\",").unwrap();
+    writeln!(text, "  \"keymap\": \"default\",").unwrap();
+    writeln!(text, "  \"layout\": \"{LAYOUT}\",").unwrap();
+    writeln!(text, "  \"layers\": [\n{}\n  ]", layers.join(",\n")).unwrap();
+    text += "}\n";
+    text
+}
+
+fn keycode_name(side: Side, layer: usize, key: RawKey) -> String {
+    let keycode = via::raw_to_keycode(key);
+    if keycode == 0 && key != NO_EVENT {
+        eprintln!(
+            "warning: {} layer {layer}: `{}` has no QMK keycode, exported as KC_NO",
+            side.name(),
+            token(key)
+        );
+    }
+    KEYCODES
+        .iter()
+        .find(|&&(_, code)| code == keycode)
+        .map_or_else(|| format!("0x{keycode:04X}"), |(name, _)| name.to_string())
+}
+
+pub fn import(text: &str) -> Result<Keymap, Error> {
+    let doc: Value = serde_json::from_str(text).map_err(|error| Error(error.to_string()))?;
+    let layers = doc["layers"]
+        .as_array()
+        .ok_or_else(|| Error(String::from("no `layers` array")))?;
+
+    let mut keymap = Keymap::default();
+    for (i, layer) in layers.iter().enumerate() {
+        let keys = layer
+            .as_array()
+            .filter(|keys| keys.len() == 2 * MATRIX_LEN)
+            .ok_or_else(|| Error(format!("layer {i}: expected {} keycodes", 2 * MATRIX_LEN)))?
+            .iter()
+            .map(parse_keycode)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| Error(format!("layer {i}: {error}")))?;
+
+        for (side, matrix) in Side::ALL.into_iter().zip(keys.chunks(MATRIX_LEN)) {
+            let name = format!("Layer {i}");
+            let layer = Layer::from_matrix(name.clone(), side, matrix)
+                .map_err(|error| Error(format!("{}: {error}", layer_context(side, i, &name))))?;
+            keymap.layers_mut(side).push(layer);
+        }
+    }
+
+    for side in Side::ALL {
+        let layers = keymap.layers_mut(side);
+        while layers.len() > 1
+            && layers
+                .last()
+                .unwrap()
+                .keys
+                .iter()
+                .all(|&key| key == NO_EVENT)
+        {
+            layers.pop();
+        }
+    }
+    Ok(keymap)
+}
+
+fn parse_keycode(value: &Value) -> Result<RawKey, String> {
+    let name = value
+        .as_str()
+        .ok_or_else(|| format!("keycode {value} is not a string"))?;
+    if TRANSPARENT.contains(&name) {
+        return Ok(NO_EVENT);
+    }
+    let keycode = KEYCODES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|&(_, code)| code)
+        .or_else(|| u16::from_str_radix(name.strip_prefix("0x")?, 16).ok())
+        .ok_or_else(|| format!("unknown keycode `{name}`"))?;
+    via::keycode_to_raw(keycode)
+        .filter(|&key| keycodes::name(key).is_some())
+        .ok_or_else(|| format!("`{name}` has no counterpart in the firmware"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn default_keymap() -> Keymap {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../keyboard-core/keymap.toml");
+        Keymap::load(&path).unwrap()
+    }
+
+    #[test]
+    fn round_trip_keeps_keys_with_a_qmk_keycode() {
+        let keymap = default_keymap();
+        let imported = import(&export(&keymap)).unwrap();
+
+        for side in Side::ALL {
+            assert_eq!(imported.layers(side).len(), keymap.layers(side).len());
+            for (layer, back) in keymap.layers(side).iter().zip(imported.layers(side)) {
+                for (&key, &key_back) in layer.keys.iter().zip(&back.keys) {
+                    let expected = match via::raw_to_keycode(key) {
+                        0 => NO_EVENT,
+                        _ => key,
+                    };
+                    assert_eq!(key_back, expected, "{} {}", side.name(), layer.name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_of_an_exported_keymap_is_exact() {
+        // Names are lost, QMK layers have none
+        let keymap = import(&export(&default_keymap())).unwrap();
+        for side in Side::ALL {
+            for (i, layer) in keymap.layers(side).iter().enumerate() {
+                assert_eq!(layer.name, format!("Layer {i}"));
+            }
+        }
+        assert_eq!(import(&export(&keymap)).unwrap(), keymap);
+    }
+
+    #[test]
+    fn transparent_keys_import_as_no_key() {
+        let mut layer = vec!["\"KC_A\""; 2 * MATRIX_LEN];
+        layer[1] = "\"KC_TRNS\"";
+        layer[2] = "\"_______\"";
+        // Matrix positions without a key, 24 on the left and 29 on the right
+        layer[24] = "\"KC_NO\"";
+        layer[MATRIX_LEN + 29] = "\"KC_NO\"";
+        let text = format!("{{\"layers\": [[{}]]}}", layer.join(", "));
+
+        let keymap = import(&text).unwrap();
+        assert_eq!(keymap.left[0].matrix(Side::Left)[1], NO_EVENT);
+        assert_eq!(keymap.left[0].matrix(Side::Left)[2], NO_EVENT);
+        assert_ne!(keymap.left[0].matrix(Side::Left)[3], NO_EVENT);
+    }
+
+    #[test]
+    fn key_bound_where_there_is_none_is_rejected() {
+        let layer = vec!["\"KC_A\""; 2 * MATRIX_LEN];
+        let text = format!("{{\"layers\": [[{}]]}}", layer.join(", "));
+        assert!(import(&text).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+//! SVG drawing of the keymap, a block per layer: the layers of the left half
+//! in the left column, those of the right half in the right one. Keys carry
+//! the labels of the text diagrams.
+
+use keymap_tool::{label, layer_context, Keymap, Side, MATRIX_COLUMNS, ROW_LENGTHS};
+use std::fmt::Write;
+
+/// Distance between neighbouring keys
+const PITCH: usize = 56;
+const KEY_SIZE: usize = 52;
+const TITLE_HEIGHT: usize = 24;
+const MARGIN: usize = 16;
+
+pub fn draw(keymap: &Keymap) -> String {
+    let block_width = MATRIX_COLUMNS * PITCH;
+    let block_height = TITLE_HEIGHT + ROW_LENGTHS.len() * PITCH + MARGIN;
+    let layer_count = keymap.left.len().max(keymap.right.len());
+    let width = 2 * block_width + 3 * MARGIN;
+    let height = layer_count * block_height + MARGIN;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    )
+    .unwrap();
+    svg += "<style>rect{fill:#f4f4f4;stroke:#444}text{font-family:monospace;\
+            text-anchor:middle;dominant-baseline:central}.title{text-anchor:start;\
+            font-size:14px;font-weight:bold}</style>\n";
+    writeln!(
+        svg,
+        r#"<rect x="0" y="0" width="{width}" height="{height}" style="fill:#fff;stroke:none"/>"#
+    )
+    .unwrap();
+
+    for (s, side) in Side::ALL.into_iter().enumerate() {
+        let left = MARGIN + s * (block_width + MARGIN);
+        for (i, layer) in keymap.layers(side).iter().enumerate() {
+            let top = MARGIN + i * block_height;
+            writeln!(
+                svg,
+                r#"<text class="title" x="{left}" y="{}">{}</text>"#,
+                top + TITLE_HEIGHT / 2,
+                escape(&layer_context(side, i, &layer.name))
+            )
+            .unwrap();
+
+            for (r, row) in layer.rows().iter().enumerate() {
+                for (column, &key) in side.columns(r).zip(row.iter()) {
+                    let x = left + column * PITCH;
+                    let y = top + TITLE_HEIGHT + r * PITCH;
+                    writeln!(
+                        svg,
+                        r#"<rect x="{x}" y="{y}" width="{KEY_SIZE}" height="{KEY_SIZE}" rx="4"/>"#
+                    )
+                    .unwrap();
+
+                    let label = label(key);
+                    if !label.is_empty() {
+                        writeln!(
+                            svg,
+                            r#"<text x="{}" y="{}" font-size="{}">{}</text>"#,
+                            x + KEY_SIZE / 2,
+                            y + KEY_SIZE / 2,
+                            font_size(&label),
+                            escape(&label)
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+        }
+    }
+    svg += "</svg>\n";
+    svg
+}
+
+/// Shrinks long labels to the width of a key
+fn font_size(label: &str) -> usize {
+    match label.len() {
+        0..=6 => 12,
+        len => (80 / len).max(5),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,9 @@
+/* Linker script for the STM32F103C8T6 bootloader, see shared-src/src/boot.rs */
+MEMORY
+{
+  /* The page after the bootloader holds the application descriptor */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 15K
+  /* The last 8 bytes pass the bootloader request from the application.
+     8, not 4: cortex-m-rt wants the stack start 8-byte aligned */
+  RAM : ORIGIN = 0x20000000, LENGTH = 20K - 8
+}
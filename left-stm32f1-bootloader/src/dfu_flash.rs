@@ -0,0 +1,136 @@
+//! Application flash region exposed through DFU
+
+use cortex_m::peripheral::SCB;
+use shared_src::boot::{
+    crc32, AppDescriptor, APP_DESCRIPTOR_ADDRESS, APP_END, APP_START, FLASH_START, PAGE_LEN,
+};
+use stm32f1xx_hal::flash::FlashWriter;
+use usbd_dfu::{DFUManifestationError, DFUMemError, DFUMemIO};
+
+const TRANSFER_LEN: usize = 128;
+
+pub struct DfuFlash<'a> {
+    writer: FlashWriter<'a>,
+    buffer: [u8; TRANSFER_LEN],
+    /// End of the highest block programmed, the length covered by the CRC
+    app_end: u32,
+    download_started: bool,
+}
+
+impl<'a> DfuFlash<'a> {
+    pub fn new(writer: FlashWriter<'a>) -> Self {
+        Self {
+            writer,
+            buffer: [0xFF; TRANSFER_LEN],
+            app_end: APP_START,
+            download_started: false,
+        }
+    }
+
+    /// Zeroes the descriptor before the first change to the application, an
+    /// interrupted download then keeps the bootloader in DFU mode. Zeroing
+    /// programs the erased page without erasing it
+    fn start_download(&mut self) -> Result<(), DFUMemError> {
+        if self.download_started {
+            return Ok(());
+        }
+
+        let descriptor = AppDescriptor::Incomplete.pack();
+        self.writer
+            .erase(APP_DESCRIPTOR_ADDRESS - FLASH_START, PAGE_LEN as usize)
+            .and_then(|()| self.writer.write(APP_DESCRIPTOR_ADDRESS - FLASH_START, &descriptor))
+            .map_err(|_| DFUMemError::Write)?;
+        self.download_started = true;
+        self.app_end = APP_START;
+        Ok(())
+    }
+}
+
+fn check_range(address: u32, len: usize) -> Result<(), DFUMemError> {
+    match address.checked_add(len as u32) {
+        Some(end) if address >= APP_START && end <= APP_END => Ok(()),
+        _ => Err(DFUMemError::Address),
+    }
+}
+
+impl DFUMemIO for DfuFlash<'_> {
+    const INITIAL_ADDRESS_POINTER: u32 = APP_START;
+    const MEM_INFO_STRING: &'static str = "@Application/0x08004000/109*1Kg";
+    const HAS_DOWNLOAD: bool = true;
+    const HAS_UPLOAD: bool = true;
+    const TRANSFER_SIZE: u16 = TRANSFER_LEN as u16;
+    // Typical page erase and half-word programming times of RM0008, rounded up
+    const PROGRAM_TIME_MS: u32 = 8;
+    const ERASE_TIME_MS: u32 = 40;
+    const FULL_ERASE_TIME_MS: u32 = 40 * 109;
+
+    fn read(&mut self, address: u32, length: usize) -> Result<&[u8], DFUMemError> {
+        // Uploads may ask past the end, they get what is left
+        let length = length.min(APP_END.saturating_sub(address) as usize);
+        check_range(address, length)?;
+        Ok(unsafe { core::slice::from_raw_parts(address as *const u8, length) })
+    }
+
+    fn erase(&mut self, address: u32) -> Result<(), DFUMemError> {
+        check_range(address, PAGE_LEN as usize)?;
+        self.start_download()?;
+        let page = address & !(PAGE_LEN - 1);
+        self.writer
+            .erase(page - FLASH_START, PAGE_LEN as usize)
+            .map_err(|_| DFUMemError::Erase)
+    }
+
+    fn erase_all(&mut self) -> Result<(), DFUMemError> {
+        self.start_download()?;
+        self.writer
+            .erase(APP_START - FLASH_START, (APP_END - APP_START) as usize)
+            .map_err(|_| DFUMemError::Erase)
+    }
+
+    fn store_write_buffer(&mut self, src: &[u8]) -> Result<(), ()> {
+        let buffer = self.buffer.get_mut(..src.len()).ok_or(())?;
+        buffer.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn program(&mut self, address: u32, length: usize) -> Result<(), DFUMemError> {
+        // Flash is programmed in half-words, an odd last block gets an
+        // erased padding byte
+        let padded = length + length % 2;
+        if padded > TRANSFER_LEN {
+            return Err(DFUMemError::Prog);
+        }
+        if length % 2 == 1 {
+            self.buffer[length] = 0xFF;
+        }
+        check_range(address, padded)?;
+        self.start_download()?;
+
+        self.writer
+            .write(address - FLASH_START, &self.buffer[..padded])
+            .map_err(|_| DFUMemError::Prog)?;
+        self.app_end = self.app_end.max(address + length as u32);
+        Ok(())
+    }
+
+    fn manifestation(&mut self) -> Result<(), DFUManifestationError> {
+        if self.download_started {
+            let len = self.app_end - APP_START;
+            let app = unsafe { core::slice::from_raw_parts(APP_START as *const u8, len as usize) };
+            let descriptor = AppDescriptor::Complete {
+                len,
+                crc: crc32(app),
+            };
+            self.writer
+                .erase(APP_DESCRIPTOR_ADDRESS - FLASH_START, PAGE_LEN as usize)
+                .and_then(|()| {
+                    self.writer
+                        .write(APP_DESCRIPTOR_ADDRESS - FLASH_START, &descriptor.pack())
+                })
+                .map_err(|_| DFUManifestationError::Prog)?;
+        }
+
+        // Not manifestation tolerant: start the new application right away
+        SCB::sys_reset()
+    }
+}
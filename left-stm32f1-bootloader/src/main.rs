@@ -0,0 +1,129 @@
+//! USB DFU bootloader of the left half (STM32F103).
+//!
+//! Flashed once with a debug probe (`cargo flash --chip STM32F103C8T6
+//! --release`), afterwards the application is updated over USB:
+//!
+//! ```text
+//! dfu-util -d 1209:0001 -a 0 -s 0x08004000:leave -D firmware.bin
+//! ```
+//!
+//! The bootloader stays in DFU mode when the application asked for it (the
+//! bootloader key or configuration command) or when the flashed application
+//! doesn't pass the checks of [`app_is_valid`]. Otherwise it jumps to the
+//! application right away, before touching any peripheral.
+
+#![no_main]
+#![no_std]
+
+use panic_reset as _;
+
+use cortex_m::asm::delay;
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::entry;
+use shared_src::boot::{
+    crc32, AppDescriptor, APP_DESCRIPTOR_ADDRESS, APP_END, APP_START, BOOTLOADER_MAGIC,
+    BOOTLOADER_REQUEST_ADDRESS,
+};
+use stm32f1xx_hal::flash::{FlashSize, SectorSize};
+use stm32f1xx_hal::usb::{Peripheral, UsbBus};
+use stm32f1xx_hal::{pac, prelude::*};
+use usb_device::prelude::*;
+use usbd_dfu::DFUClass;
+
+mod dfu_flash;
+
+use dfu_flash::DfuFlash;
+
+#[entry]
+fn main() -> ! {
+    if !bootloader_requested() && app_is_valid() {
+        unsafe { jump_to_app() }
+    }
+
+    let dp = pac::Peripherals::take().unwrap_or_else(|| panic!());
+
+    let mut flash = dp.FLASH.constrain();
+    let rcc = dp.RCC.constrain();
+
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.MHz())
+        .sysclk(48.MHz())
+        .pclk1(24.MHz())
+        .freeze(&mut flash.acr);
+
+    let mut gpioa = dp.GPIOA.split();
+    let mut gpioc = dp.GPIOC.split();
+
+    // On-board LED (PC13, active low) shows the DFU mode
+    let mut led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh);
+    led.set_low();
+
+    // Same forced reset as the application, the host has to notice that the
+    // application went away
+    let mut usb_dp = gpioa.pa12.into_push_pull_output(&mut gpioa.crh);
+    usb_dp.set_low();
+    delay(clocks.sysclk().raw() / 100);
+
+    let usb = Peripheral {
+        usb: dp.USB,
+        pin_dm: gpioa.pa11,
+        pin_dp: usb_dp.into_floating_input(&mut gpioa.crh),
+    };
+    let usb_bus = UsbBus::new(usb);
+
+    let writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz128K);
+    let mut dfu = DFUClass::new(&usb_bus, DfuFlash::new(writer));
+
+    // Same IDs as the application defaults, see left-stm32f1/src/usb_identity.rs
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x1209, 0x0001))
+        .strings(&[StringDescriptors::default()
+            .manufacturer("VirhPotujnosti")
+            .product("VirhPotujnosti Bootloader")])
+        .unwrap_or_else(|_| panic!())
+        .build();
+
+    loop {
+        usb_dev.poll(&mut [&mut dfu]);
+    }
+}
+
+/// Takes the request left by the application, so the next reset starts it
+/// again
+fn bootloader_requested() -> bool {
+    let request = BOOTLOADER_REQUEST_ADDRESS as *mut u32;
+    unsafe {
+        let requested = request.read_volatile() == BOOTLOADER_MAGIC;
+        request.write_volatile(0);
+        requested
+    }
+}
+
+/// A downloaded application must match the CRC written after its download,
+/// one flashed with a debug probe only needs a plausible vector table
+fn app_is_valid() -> bool {
+    let descriptor = unsafe { &*(APP_DESCRIPTOR_ADDRESS as *const [u8; AppDescriptor::LEN]) };
+    let vector_table_valid = {
+        let initial_sp = unsafe { (APP_START as *const u32).read_volatile() };
+        let reset_vector = unsafe { (APP_START as *const u32).add(1).read_volatile() };
+        (0x2000_0000..=BOOTLOADER_REQUEST_ADDRESS).contains(&initial_sp)
+            && (APP_START..APP_END).contains(&reset_vector)
+    };
+
+    match AppDescriptor::unpack(descriptor) {
+        AppDescriptor::Unverified => vector_table_valid,
+        AppDescriptor::Incomplete => false,
+        AppDescriptor::Complete { len, crc } => {
+            if len == 0 || len > APP_END - APP_START {
+                return false;
+            }
+            let app = unsafe { core::slice::from_raw_parts(APP_START as *const u8, len as usize) };
+            vector_table_valid && crc32(app) == crc
+        }
+    }
+}
+
+unsafe fn jump_to_app() -> ! {
+    (*SCB::PTR).vtor.write(APP_START);
+    cortex_m::asm::bootload(APP_START as *const u32)
+}
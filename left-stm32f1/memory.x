@@ -1,6 +1,11 @@
 /* Linker script for the STM32F103C8T6 */
 MEMORY
 {
-  FLASH : ORIGIN = 0x08000000, LENGTH = 128K
-  RAM : ORIGIN = 0x20000000, LENGTH = 20K
-}
\ No newline at end of file
+  /* The DFU bootloader (left-stm32f1-bootloader) and the application
+     descriptor come first, the last three 1K pages hold the keymap and the
+     settings, see src/keymap_storage.rs and src/settings.rs */
+  FLASH : ORIGIN = 0x08004000, LENGTH = 109K
+  /* The last 8 bytes pass the bootloader request, see src/bootloader.rs.
+     8, not 4: cortex-m-rt wants the stack start 8-byte aligned */
+  RAM : ORIGIN = 0x20000000, LENGTH = 20K - 8
+}
@@ -0,0 +1,148 @@
+//! STM32F103C8 board of the left half (Blue Pill): 8 MHz crystal, LED on
+//! PC13, UART to the right half on USART3 (PB10/PB11), matrix columns on
+//! PB9..PB5 and rows on PA1..PA6.
+
+use crate::bootloader;
+use crate::internal_flash::InternalFlash;
+use cortex_m::asm::delay;
+use keyboard_core::board::{Board, HostBoard, HostParts, Led, Matrix, Parts};
+use stm32f1xx_hal::flash::{self, FlashSize, SectorSize};
+use stm32f1xx_hal::gpio::{ErasedPin, Input, Output, PullDown, PushPull};
+use stm32f1xx_hal::serial::{Config, Rx, Tx};
+use stm32f1xx_hal::timer::DelayUs;
+use stm32f1xx_hal::usb::{Peripheral, UsbBus, UsbBusType};
+use stm32f1xx_hal::{pac, prelude::*};
+
+/// 96-bit unique device ID
+const UID_ADDRESS: *const u8 = 0x1FFF_F7E8 as *const u8;
+const UID_LEN: usize = 12;
+
+pub struct Stm32f103;
+
+impl Board for Stm32f103 {
+    type Column = ErasedPin<Output<PushPull>>;
+    type Row = ErasedPin<Input<PullDown>>;
+    type LedPin = ErasedPin<Output<PushPull>>;
+    type LinkTx = Tx<pac::USART3>;
+    type LinkRx = Rx<pac::USART3>;
+    type Delay = DelayUs<pac::TIM3>;
+
+    const SYSCLK_HZ: u32 = 48_000_000;
+
+    fn init() -> Parts<Self> {
+        Self::init_host().0
+    }
+
+    fn reboot_to_bootloader() -> ! {
+        bootloader::reboot_to_bootloader()
+    }
+}
+
+impl HostBoard for Stm32f103 {
+    type UsbBus = UsbBusType;
+    type Flash = InternalFlash<'static>;
+
+    fn init_host() -> (Parts<Self>, HostParts<Self>) {
+        // Get access to the device specific peripherals from the peripheral access crate
+        let dp = pac::Peripherals::take().unwrap_or_else(|| panic!());
+
+        // The flash writer borrows the flash for as long as the firmware runs
+        let flash =
+            cortex_m::singleton!(: flash::Parts = dp.FLASH.constrain()).unwrap_or_else(|| panic!());
+        let rcc = dp.RCC.constrain();
+
+        let clocks = rcc
+            .cfgr
+            .use_hse(8.MHz())
+            .sysclk(Self::SYSCLK_HZ.Hz())
+            .pclk1(24.MHz())
+            .freeze(&mut flash.acr);
+
+        let mut gpioa = dp.GPIOA.split();
+        let mut gpiob = dp.GPIOB.split();
+        let mut gpioc = dp.GPIOC.split();
+
+        // On-board LED, active low
+        let led = gpioc.pc13.into_push_pull_output(&mut gpioc.crh).erase();
+
+        /////// Init UART ///////
+        let tx = gpiob.pb10.into_alternate_push_pull(&mut gpiob.crh);
+        let rx = gpiob.pb11;
+        let (link_tx, mut link_rx) = dp
+            .USART3
+            .serial((tx, rx), Config::default().baudrate(57600.bps()), &clocks)
+            .split();
+        link_rx.listen();
+
+        /////// Init USB ///////
+        // This code taken from the examples
+        // BluePill board has a pull-up resistor on the D+ line.
+        // Pull the D+ pin down to send a RESET condition to the USB bus.
+        // This forced reset is needed only for development, without it host
+        // will not reset your device when you upload new firmware.
+        let mut usb_dp = gpioa.pa12.into_push_pull_output(&mut gpioa.crh);
+        usb_dp.set_low();
+        delay(clocks.sysclk().raw() / 100);
+
+        let usb = Peripheral {
+            usb: dp.USB,
+            pin_dm: gpioa.pa11,
+            pin_dp: usb_dp.into_floating_input(&mut gpioa.crh),
+        };
+        let usb_bus = UsbBus::new(usb);
+
+        // Collumns
+        let power_pins = [
+            gpiob.pb9.into_push_pull_output(&mut gpiob.crh).erase(),
+            gpiob.pb8.into_push_pull_output(&mut gpiob.crh).erase(),
+            gpiob.pb7.into_push_pull_output(&mut gpiob.crl).erase(),
+            gpiob.pb6.into_push_pull_output(&mut gpiob.crl).erase(),
+            gpiob.pb5.into_push_pull_output(&mut gpiob.crl).erase(),
+        ];
+
+        // Rows
+        let signal_pins = [
+            gpioa.pa1.into_pull_down_input(&mut gpioa.crl).erase(),
+            gpioa.pa2.into_pull_down_input(&mut gpioa.crl).erase(),
+            gpioa.pa3.into_pull_down_input(&mut gpioa.crl).erase(),
+            gpioa.pa4.into_pull_down_input(&mut gpioa.crl).erase(),
+            gpioa.pa5.into_pull_down_input(&mut gpioa.crl).erase(),
+            gpioa.pa6.into_pull_down_input(&mut gpioa.crl).erase(),
+        ];
+
+        let parts = Parts {
+            matrix: Matrix::new(power_pins, signal_pins),
+            link_tx,
+            link_rx,
+            delay: dp.TIM3.delay_us(&clocks),
+            led: Some(Led::new(led, true)),
+        };
+        let host = HostParts {
+            usb_bus,
+            flash: InternalFlash::new(flash.writer(SectorSize::Sz1K, FlashSize::Sz128K)),
+        };
+        (parts, host)
+    }
+
+    fn unique_id() -> &'static [u8] {
+        // Factory programmed system memory, always readable
+        unsafe { core::slice::from_raw_parts(UID_ADDRESS, UID_LEN) }
+    }
+
+    /// See RM0008 "Suspend/Resume events"
+    fn signal_resume(on: bool) {
+        let usb = unsafe { &*pac::USB::ptr() };
+        if on {
+            usb.cntr().modify(|_, w| {
+                w.fsusp()
+                    .clear_bit()
+                    .lpmode()
+                    .clear_bit()
+                    .resume()
+                    .set_bit()
+            });
+        } else {
+            usb.cntr().modify(|_, w| w.resume().clear_bit());
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//! Reboot into the USB DFU bootloader in the first flash pages, see
+//! `left-stm32f1-bootloader`.
+//!
+//! The request survives the reset at the top of RAM, which `memory.x` leaves
+//! out of the RAM region, and the bootloader checks it before jumping
+//! to the application
+
+use cortex_m::peripheral::SCB;
+use shared_src::boot::{BOOTLOADER_MAGIC, BOOTLOADER_REQUEST_ADDRESS};
+
+pub fn reboot_to_bootloader() -> ! {
+    unsafe {
+        (BOOTLOADER_REQUEST_ADDRESS as *mut u32).write_volatile(BOOTLOADER_MAGIC);
+    }
+    SCB::sys_reset()
+}
@@ -0,0 +1,79 @@
+use crate::via;
+use keyboard_core::layouts_def::{self, MultiKey};
+use shared_src::config_protocol::{
+    Command, DiagnosticCounters, FirmwareVersion, Report, Request, Response, Status,
+    PROTOCOL_VERSION,
+};
+
+pub const FIRMWARE_VERSION: FirmwareVersion = FirmwareVersion::parse(env!("CARGO_PKG_VERSION"));
+
+/// What the keymap task has to do once the response is sent
+#[derive(Copy, Clone, PartialEq)]
+pub enum Action {
+    None,
+    RebootToBootloader,
+    /// Forward the bootloader request to the right half
+    RightHalfBootloader,
+}
+
+/// Requests with an id outside of our [`Command`] range go to the VIA handler
+pub fn handle_request(
+    request: &Report,
+    counters: &DiagnosticCounters,
+    uptime_ms: u32,
+    response: &mut Report,
+) -> Action {
+    if !(Command::FIRST..=Command::LAST).contains(&request[0]) {
+        response.copy_from_slice(request);
+        return via::handle_request(response, uptime_ms);
+    }
+
+    let (result, action) = match Request::parse(request) {
+        Ok(request) => execute(request, counters),
+        Err(status) => (Response::Error(status), Action::None),
+    };
+    result.write(request[0], response);
+    action
+}
+
+fn execute(request: Request, counters: &DiagnosticCounters) -> (Response, Action) {
+    let response = match request {
+        Request::GetVersion => Response::Version {
+            protocol: PROTOCOL_VERSION,
+            firmware: FIRMWARE_VERSION,
+        },
+        Request::GetKeymapEntry(position) => match layouts_def::get_key(position) {
+            Ok(key) => Response::KeymapEntry(key.to_raw()),
+            Err(status) => Response::Error(status),
+        },
+        Request::SetKeymapEntry(position, raw) => {
+            let result = MultiKey::from_raw(raw)
+                .ok_or(Status::InvalidArgument)
+                .and_then(|key| layouts_def::set_key(position, key));
+            match result {
+                Ok(()) => Response::Done,
+                Err(status) => Response::Error(status),
+            }
+        }
+        Request::GetMatrix => {
+            let (left, right) = layouts_def::matrix_state();
+            Response::Matrix {
+                left: left.get_raw(),
+                right: right.get_raw(),
+            }
+        }
+        Request::GetCounters => Response::Counters(DiagnosticCounters {
+            rollovers: layouts_def::rollover_count(),
+            ..*counters
+        }),
+        Request::RebootToBootloader(side) => {
+            let action = match side {
+                0 => Action::RebootToBootloader,
+                1 => Action::RightHalfBootloader,
+                _ => return (Response::Error(Status::InvalidArgument), Action::None),
+            };
+            return (Response::Done, action);
+        }
+    };
+    (response, Action::None)
+}
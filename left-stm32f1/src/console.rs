@@ -0,0 +1,297 @@
+//! Line based debug console on a CDC-ACM interface, built with the `console`
+//! feature. Type `help` in a terminal for the list of commands.
+//!
+//! The USB interrupt moves the characters, the commands run in the keymap
+//! task: they read and change the engine state of `layouts_def`, which only
+//! that task may touch.
+
+use core::fmt::{self, Write};
+
+use crate::config_channel::Action;
+use keyboard_core::fixed_vec::FixedVec;
+use keyboard_core::layouts_def::{self, MultiKey};
+use shared_src::config_protocol::{DiagnosticCounters, KeyKind, KeyPosition, RawKey};
+use shared_src::PrimitiveBitset;
+use usb_device::bus::{UsbBus, UsbBusAllocator};
+use usbd_serial::SerialPort;
+
+const LINE_LEN: usize = 64;
+const OUTPUT_LEN: usize = 1024;
+
+const HELP: &str = "\
+matrix                          live matrix of both halves\r
+layer                           active layers\r
+stats                           split link and report counters\r
+keymap <side> <layer>           dump a layer, side is 0 left, 1 right\r
+get <side> <layer> <index>      show a keymap entry\r
+set <side> <layer> <index> <kind> <code>\r
+                                kind: key consumer system move button wheel command\r
+debug on|off                    log key and link events\r
+bootloader <side>               reboot a half into its bootloader\r
+";
+
+const KIND_NAMES: [(KeyKind, &str); 7] = [
+    (KeyKind::Keyboard, "key"),
+    (KeyKind::Consumer, "consumer"),
+    (KeyKind::System, "system"),
+    (KeyKind::MouseMove, "move"),
+    (KeyKind::MouseButton, "button"),
+    (KeyKind::MouseWheel, "wheel"),
+    (KeyKind::Command, "command"),
+];
+
+pub struct Console<'a, B: UsbBus> {
+    serial: SerialPort<'a, B>,
+    line: FixedVec<u8, LINE_LEN>,
+    /// Complete line waiting for [`Console::run`], input stops meanwhile
+    pending: Option<FixedVec<u8, LINE_LEN>>,
+    /// Text not taken by the endpoint yet, anything past it is dropped
+    output: FixedVec<u8, OUTPUT_LEN>,
+    debug: bool,
+    action: Option<Action>,
+}
+
+impl<'a, B: UsbBus> Console<'a, B> {
+    pub fn new(usb_bus: &'a UsbBusAllocator<B>) -> Self {
+        Self {
+            serial: SerialPort::new(usb_bus),
+            line: FixedVec::new(0),
+            pending: None,
+            output: FixedVec::new(0),
+            debug: false,
+            action: None,
+        }
+    }
+
+    /// Has to be passed to `UsbDevice::poll` with the other classes
+    pub fn serial(&mut self) -> &mut SerialPort<'a, B> {
+        &mut self.serial
+    }
+
+    /// Writes a debug event when logging is enabled with `debug on`
+    pub fn log(&mut self, args: fmt::Arguments) {
+        if self.debug {
+            let _ = self.write_fmt(args);
+            let _ = self.write_str("\r\n");
+        }
+    }
+
+    /// Reads typed characters and sends pending output, from the USB
+    /// interrupt. Returns whether a complete line waits for [`Console::run`]
+    pub fn poll(&mut self) -> bool {
+        // Input waits in the endpoint while a line waits or the output of
+        // earlier commands is still being sent
+        let mut received = [0u8; 32];
+        if self.pending.is_none() && self.output.len() <= OUTPUT_LEN / 2 {
+            if let Ok(count) = self.serial.read(&mut received) {
+                for &byte in &received[..count] {
+                    self.receive(byte);
+                }
+            }
+        }
+        self.flush();
+        self.pending.is_some()
+    }
+
+    /// Runs the line completed by [`Console::poll`], from the keymap task.
+    /// Returns what the keymap task has to do for the command
+    pub fn run(&mut self, counters: &DiagnosticCounters) -> Option<Action> {
+        let line = self.pending.take()?;
+        if let Ok(line) = core::str::from_utf8(&line) {
+            self.execute(line, counters);
+        }
+        let _ = self.write_str("> ");
+        self.flush();
+        self.action.take()
+    }
+
+    /// Sends as much of the output as the endpoint takes
+    pub fn flush(&mut self) {
+        if let Ok(written) = self.serial.write(&self.output) {
+            let len = self.output.len();
+            self.output.copy_within(written..len, 0);
+            self.output.truncate(len - written);
+        }
+    }
+
+    fn receive(&mut self, byte: u8) {
+        match byte {
+            b'\r' | b'\n' => {
+                let _ = self.write_str("\r\n");
+                // Several lines in one read only happen when pasting
+                match self.pending {
+                    None => self.pending = Some(self.line),
+                    Some(_) => {
+                        let _ = self.write_str("busy, line dropped\r\n");
+                    }
+                }
+                self.line.clear();
+            }
+            // Backspace and delete
+            0x08 | 0x7F if self.line.pop().is_some() => {
+                let _ = self.write_str("\x08 \x08");
+            }
+            b' '..=b'~' if self.line.try_push(byte).is_ok() => {
+                let _ = self.write_char(byte as char);
+            }
+            _ => {}
+        }
+    }
+
+    fn execute(&mut self, line: &str, counters: &DiagnosticCounters) {
+        let mut args = line.split_ascii_whitespace();
+        // Output that doesn't fit is dropped
+        let _ = match args.next() {
+            None => Ok(()),
+            Some("help") => self.write_str(HELP),
+            Some("matrix") => self.show_matrix(),
+            Some("layer") => {
+                let (left, right) = layouts_def::active_layers();
+                write!(self, "left {left}, right {right}\r\n")
+            }
+            Some("stats") => write!(
+                self,
+                "link packets {}, link errors {}, reports sent {}, report retries {}, rollovers {}\r\n",
+                counters.link_packets,
+                counters.link_errors,
+                counters.reports_sent,
+                counters.report_retries,
+                layouts_def::rollover_count(),
+            ),
+            Some("keymap") => match (parse_u8(args.next()), parse_u8(args.next())) {
+                (Some(side), Some(layer)) => self.show_layer(side, layer),
+                _ => self.write_str("usage: keymap <side> <layer>\r\n"),
+            },
+            Some("get") => match parse_position(&mut args) {
+                Some(position) => self.show_entry(position),
+                None => self.write_str("usage: get <side> <layer> <index>\r\n"),
+            },
+            Some("set") => self.set_entry(&mut args),
+            Some("debug") => match args.next() {
+                Some("on") => {
+                    self.debug = true;
+                    Ok(())
+                }
+                Some("off") => {
+                    self.debug = false;
+                    Ok(())
+                }
+                _ => self.write_str("usage: debug on|off\r\n"),
+            },
+            Some("bootloader") => match parse_u8(args.next()) {
+                Some(0) => {
+                    self.action = Some(Action::RebootToBootloader);
+                    Ok(())
+                }
+                Some(1) => {
+                    self.action = Some(Action::RightHalfBootloader);
+                    Ok(())
+                }
+                _ => self.write_str("usage: bootloader <side>\r\n"),
+            },
+            Some(_) => self.write_str("unknown command, see help\r\n"),
+        };
+    }
+
+    fn show_matrix(&mut self) -> fmt::Result {
+        let (left, right) = layouts_def::matrix_state();
+        let row_bits = |matrix: PrimitiveBitset<u32>, row: usize| {
+            let mut bits = [b'.'; 6];
+            for (col, bit) in bits.iter_mut().enumerate() {
+                if matrix.get(row * 6 + col) {
+                    *bit = b'#';
+                }
+            }
+            bits
+        };
+        for row in 0..5 {
+            let (left_bits, right_bits) = (row_bits(left, row), row_bits(right, row));
+            // Both only hold ASCII
+            let left_text = core::str::from_utf8(&left_bits).unwrap_or_default();
+            let right_text = core::str::from_utf8(&right_bits).unwrap_or_default();
+            write!(self, "{left_text}  {right_text}\r\n")?;
+        }
+        Ok(())
+    }
+
+    fn show_layer(&mut self, side: u8, layer: u8) -> fmt::Result {
+        for index in 0..30 {
+            let position = KeyPosition { side, layer, index };
+            match layouts_def::get_key(position) {
+                Ok(key) => write_key(self, key.to_raw())?,
+                Err(_) => return self.write_str("no such layer\r\n"),
+            }
+            self.write_str(if index % 6 == 5 { "\r\n" } else { "  " })?;
+        }
+        Ok(())
+    }
+
+    fn show_entry(&mut self, position: KeyPosition) -> fmt::Result {
+        match layouts_def::get_key(position) {
+            Ok(key) => {
+                write_key(self, key.to_raw())?;
+                self.write_str("\r\n")
+            }
+            Err(_) => self.write_str("no such key\r\n"),
+        }
+    }
+
+    fn set_entry<'l>(&mut self, args: &mut impl Iterator<Item = &'l str>) -> fmt::Result {
+        let position = parse_position(args);
+        let kind = args
+            .next()
+            .and_then(|name| KIND_NAMES.iter().find(|(_, kind_name)| *kind_name == name))
+            .map(|&(kind, _)| kind);
+        let code = parse_number(args.next()).and_then(|code| u16::try_from(code).ok());
+        let (Some(position), Some(kind), Some(code)) = (position, kind, code) else {
+            return self.write_str("usage: set <side> <layer> <index> <kind> <code>\r\n");
+        };
+
+        let result = MultiKey::from_raw(RawKey::new(kind, code))
+            .ok_or(())
+            .and_then(|key| layouts_def::set_key(position, key).map_err(|_| ()));
+        match result {
+            Ok(()) => self.show_entry(position),
+            Err(()) => self.write_str("invalid key or position\r\n"),
+        }
+    }
+}
+
+impl<B: UsbBus> Write for Console<'_, B> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            self.output.try_push(byte).map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_key(out: &mut impl Write, key: RawKey) -> fmt::Result {
+    let name = KIND_NAMES
+        .iter()
+        .find(|(kind, _)| *kind == key.kind)
+        .map_or("?", |(_, name)| name);
+    write!(out, "{name}:{:#04x}", key.code)
+}
+
+/// Decimal or `0x` prefixed hexadecimal
+fn parse_number(arg: Option<&str>) -> Option<u32> {
+    let arg = arg?;
+    match arg.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => arg.parse().ok(),
+    }
+}
+
+fn parse_u8(arg: Option<&str>) -> Option<u8> {
+    parse_number(arg).and_then(|value| u8::try_from(value).ok())
+}
+
+fn parse_position<'l>(args: &mut impl Iterator<Item = &'l str>) -> Option<KeyPosition> {
+    let mut next = || parse_u8(args.next());
+    Some(KeyPosition {
+        side: next()?,
+        layer: next()?,
+        index: next()?,
+    })
+}
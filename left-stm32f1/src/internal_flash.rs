@@ -0,0 +1,60 @@
+//! [`NorFlash`] over the internal flash, for the storage code shared with the
+//! host. Offsets count from the start of the flash, as for [`FlashWriter`].
+
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+use stm32f1xx_hal::flash::{FlashWriter, FLASH_START};
+
+const CAPACITY: usize = 128 * 1024;
+
+pub struct InternalFlash<'a> {
+    writer: FlashWriter<'a>,
+}
+
+impl<'a> InternalFlash<'a> {
+    pub fn new(writer: FlashWriter<'a>) -> Self {
+        Self { writer }
+    }
+}
+
+impl ErrorType for InternalFlash<'_> {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for InternalFlash<'_> {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        if offset as usize + bytes.len() > CAPACITY {
+            return Err(NorFlashErrorKind::OutOfBounds);
+        }
+        // Memory mapped flash, always readable
+        let data = unsafe {
+            core::slice::from_raw_parts((FLASH_START + offset) as *const u8, bytes.len())
+        };
+        bytes.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        CAPACITY
+    }
+}
+
+impl NorFlash for InternalFlash<'_> {
+    /// Programming is done in half-words
+    const WRITE_SIZE: usize = 2;
+    const ERASE_SIZE: usize = 1024;
+
+    /// The CPU stalls for ~20 ms per page meanwhile
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        self.writer
+            .erase(from, (to - from) as usize)
+            .map_err(|_| NorFlashErrorKind::Other)
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.writer
+            .write(offset, bytes)
+            .map_err(|_| NorFlashErrorKind::Other)
+    }
+}
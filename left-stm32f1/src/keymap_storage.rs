@@ -0,0 +1,48 @@
+//! Keymap kept across power cycles in the flash page before the settings,
+//! serialised with [`shared_src::keymap_blob`].
+//!
+//! An erased or damaged page leaves the compiled default keymap in place, as
+//! do the entries a stored keymap lacks or can't express.
+
+use embedded_storage::nor_flash::NorFlash;
+use keyboard_core::layouts_def::{self, KEYMAP_SHAPE};
+use shared_src::boot::{FLASH_START, KEYMAP_ADDRESS};
+use shared_src::config_protocol::KeyPosition;
+use shared_src::keymap_blob;
+use static_assertions::const_assert;
+
+const KEYMAP_PAGE_LEN: u32 = 1024;
+// Flash is written in half-words
+const BLOB_LEN: usize = (KEYMAP_SHAPE.blob_len() + 1) & !1;
+const_assert!(BLOB_LEN <= KEYMAP_PAGE_LEN as usize);
+const KEYMAP_OFFSET: u32 = KEYMAP_ADDRESS - FLASH_START;
+
+/// Applies the stored keymap over the default one, call once at startup
+pub fn load(flash: &mut impl NorFlash) {
+    let mut data = [0xFF; KEYMAP_PAGE_LEN as usize];
+    if flash.read(KEYMAP_OFFSET, &mut data).is_err() {
+        return;
+    }
+    if let Some((shape, entries)) = keymap_blob::decode(&data) {
+        layouts_def::load_keymap(shape, entries);
+    }
+}
+
+/// Erases and rewrites the page, the CPU stalls for ~40 ms meanwhile
+pub fn store(flash: &mut impl NorFlash) {
+    let keys = (0..KEYMAP_SHAPE.entry_count()).map(|i| {
+        let (side, layer, index) = KEYMAP_SHAPE.position(i);
+        layouts_def::get_key(KeyPosition { side, layer, index })
+            .unwrap_or_else(|_| panic!())
+            .to_raw()
+    });
+    let mut blob = [0xFF; BLOB_LEN];
+    if keymap_blob::encode(KEYMAP_SHAPE, keys, &mut blob).is_none() {
+        return;
+    }
+
+    // A failed write leaves a page that loads the default keymap
+    let _ = flash
+        .erase(KEYMAP_OFFSET, KEYMAP_OFFSET + KEYMAP_PAGE_LEN)
+        .and_then(|()| flash.write(KEYMAP_OFFSET, &blob));
+}
@@ -1,196 +1,708 @@
+//! Firmware of the left half, the one plugged into the host. It runs as RTIC
+//! tasks connected by channels:
+//!
+//! - `scan` scans the left matrix every millisecond, every 10 ms while the
+//!   host sleeps
+//! - `link_receive` (UART interrupt) assembles the matrices of the right half
+//! - `keymap` turns both into reports and owns the settings and the keymap
+//!   storage, it reacts to [`Event`]s only
+//! - `usb_poll` (USB interrupt) serves the bus, `usb_write` writes the
+//!   [`UsbReport`]s, `hid_tick` drives the idle reports
+//! - `link_transmit` sends the status and commands to the right half
+//!
+//! A new task, e.g. for lighting, gets its own channel instead of holding up
+//! the scan and the reports.
+
 #![no_main]
 #![no_std]
 
 use panic_reset as _;
 
-use cortex_m::asm::delay;
-use cortex_m_rt::entry;
-use stm32f1xx_hal::usb::{Peripheral, UsbBus};
-use stm32f1xx_hal::{pac, prelude::*, serial::Config};
+use embedded_hal::delay::DelayNs;
+use embedded_hal_nb::serial::{Read, Write};
+use rtic_monotonics::systick::prelude::*;
+use rtic_sync::channel::{Receiver, Sender};
+use rtic_sync::make_channel;
+use usb_device::bus::UsbBusAllocator;
 use usb_device::prelude::*;
 
 use usbd_human_interface_device::device::consumer::{
     ConsumerControl, ConsumerControlConfig, MultipleConsumerReport,
 };
-use usbd_human_interface_device::device::keyboard::{NKROBootKeyboard, NKROBootKeyboardConfig};
+use usbd_human_interface_device::device::keyboard::{
+    KeyboardLedsReport, NKROBootKeyboard, NKROBootKeyboardConfig,
+};
+use usbd_human_interface_device::device::mouse::{WheelMouse, WheelMouseConfig, WheelMouseReport};
 
+use usbd_human_interface_device::device::DeviceClass;
+use usbd_human_interface_device::interface::{HidProtocol, InterfaceClass};
 use usbd_human_interface_device::page::{Consumer, Keyboard};
 use usbd_human_interface_device::prelude::*;
 
-use fixed_vec::FixedVec;
-use shared_src::PrimitiveBitset;
-
-mod fixed_vec;
-mod layouts_def;
-
-#[entry]
-fn main() -> ! {
-    // Get access to the device specific peripherals from the peripheral access crate
-    let dp = pac::Peripherals::take().unwrap_or_else(|| panic!());
-
-    let mut flash = dp.FLASH.constrain();
-    let rcc = dp.RCC.constrain();
-
-    let clocks = rcc
-        .cfgr
-        .use_hse(8.MHz())
-        .sysclk(48.MHz())
-        .pclk1(24.MHz())
-        .freeze(&mut flash.acr);
-
-    let mut gpioa = dp.GPIOA.split();
-    let mut gpiob = dp.GPIOB.split();
-
-    /////// Init UART ///////
-    let tx = gpiob.pb10.into_alternate_push_pull(&mut gpiob.crh);
-    let rx = gpiob.pb11;
-    let mut serial = dp
-        .USART3
-        .serial((tx, rx), Config::default().baudrate(57600.bps()), &clocks);
-
-    /////// Init USB-HID device ///////
-    // This code taken from the examples
-    // BluePill board has a pull-up resistor on the D+ line.
-    // Pull the D+ pin down to send a RESET condition to the USB bus.
-    // This forced reset is needed only for development, without it host
-    // will not reset your device when you upload new firmware.
-    let mut usb_dp = gpioa.pa12.into_push_pull_output(&mut gpioa.crh);
-    usb_dp.set_low();
-    delay(clocks.sysclk().raw() / 100);
-
-    let usb = Peripheral {
-        usb: dp.USB,
-        pin_dm: gpioa.pa11,
-        pin_dp: usb_dp.into_floating_input(&mut gpioa.crh),
-    };
-    let usb_bus = UsbBus::new(usb);
-
-    let mut keyboard = UsbHidClassBuilder::new()
-        .add_device(NKROBootKeyboardConfig::default())
-        .add_device(ConsumerControlConfig::default())
-        .build(&usb_bus);
-
-    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x05AC, 0x0202))
-        .strings(&[StringDescriptors::default()
-            .manufacturer("MegaHoholTimofeyKirichenko")
-            .product("VirhPotujnosti")
-            .serial_number("PesPatron")])
-        .unwrap_or_else(|_| panic!())
-        .build();
-
-    let mut timer = dp.TIM2.counter_hz(&clocks);
-    timer.start(1000.Hz()).unwrap_or_else(|_| panic!());
-
-    //
-    // Collumns
-    let mut power_pins = [
-        gpiob.pb9.into_push_pull_output(&mut gpiob.crh).erase(),
-        gpiob.pb8.into_push_pull_output(&mut gpiob.crh).erase(),
-        gpiob.pb7.into_push_pull_output(&mut gpiob.crl).erase(),
-        gpiob.pb6.into_push_pull_output(&mut gpiob.crl).erase(),
-        gpiob.pb5.into_push_pull_output(&mut gpiob.crl).erase(),
-    ];
-
-    // Rows
-    let signal_pins = [
-        gpioa.pa1.into_pull_down_input(&mut gpioa.crl).erase(),
-        gpioa.pa2.into_pull_down_input(&mut gpioa.crl).erase(),
-        gpioa.pa3.into_pull_down_input(&mut gpioa.crl).erase(),
-        gpioa.pa4.into_pull_down_input(&mut gpioa.crl).erase(),
-        gpioa.pa5.into_pull_down_input(&mut gpioa.crl).erase(),
-        gpioa.pa6.into_pull_down_input(&mut gpioa.crl).erase(),
-    ];
-
-    // Async UART buffer
-    let mut uart_buffer = [0u8; 5];
-    let mut uart_buffer_len = 0;
-
-    let mut left_matrix = [false; 30];
-    let mut key_report: FixedVec<_, 58> = FixedVec::new(Keyboard::NoEventIndicated);
-    let mut media_report: FixedVec<_, 4> = FixedVec::new(Consumer::Unassigned);
-
-    loop {
-        // Async reading UART data from slave to buffer
-        match serial.rx.read() {
-            Ok(received) => {
-                // Message start
-                if received & 0x80 == 0x80 {
-                    // Buffer is truly start
-                    if uart_buffer_len == 0 {
-                        uart_buffer[0] = received;
-                        uart_buffer_len = 1;
+use config_channel::Action;
+use keyboard_core::board::{Board, HostBoard, HostParts, Led, Matrix, Parts};
+use keyboard_core::fixed_vec::FixedVec;
+use keyboard_core::layouts_def::{self, KeyCommand, KeyboardMode};
+use keyboard_core::mouse_keys::MouseKeys;
+use raw_hid::{RawHid, RawHidConfig};
+use settings::Settings;
+use shared_src::config_protocol::{DiagnosticCounters, Report, REPORT_LEN};
+use shared_src::eeprom::Eeprom;
+use shared_src::storage;
+use shared_src::{
+    HostLeds, MainHalfCommand, MainHalfStatus, MatrixBitset, PrimitiveBitset, MATRIX_PACKET_LEN,
+};
+use system_control::{SystemControl, SystemControlConfig, SystemControlDevice};
+
+mod board;
+mod bootloader;
+mod config_channel;
+#[cfg(feature = "console")]
+mod console;
+mod internal_flash;
+mod keymap_storage;
+mod raw_hid;
+mod settings;
+mod system_control;
+mod usb_identity;
+mod via;
+
+/// The board the firmware is built for. A port changes it along with the
+/// device and the interrupts of the app
+type Target = board::Stm32f103;
+type Bus = <Target as HostBoard>::UsbBus;
+
+/// The HID devices in reverse order of `add_device`, which prepends
+type Hid = UsbHidClass<
+    'static,
+    Bus,
+    frunk::HList!(
+        RawHid<'static, Bus>,
+        SystemControlDevice<'static, Bus>,
+        WheelMouse<'static, Bus>,
+        ConsumerControl<'static, Bus>,
+        NKROBootKeyboard<'static, Bus>
+    ),
+>;
+
+/// Left matrix index of the bootmagic key (Escape)
+const CLEAR_CONFIG_KEY: usize = 0;
+
+/// Quiet time after a keymap edit before the keymap is written to flash
+const KEYMAP_SAVE_DELAY_MS: u32 = 2000;
+
+/// The status is resent this often in case the right half was restarted
+const STATUS_RESEND_MS: u32 = 1000;
+
+/// Capacity of the channels between the tasks
+const QUEUE_LEN: usize = 8;
+
+systick_monotonic!(Mono, 1000);
+
+/// What the keymap task reacts to
+pub enum Event {
+    /// Scan of the left matrix, one per millisecond while the host is awake
+    Left(PrimitiveBitset<u32>),
+    /// Matrix received from the right half
+    Right(PrimitiveBitset<u32>),
+    /// Broken message dropped by the link
+    LinkError,
+    HostLeds(HostLeds),
+    /// The host went to sleep or woke up, see [`Usb::suspended`]. Also
+    /// looked for on every other event, in case the queue was full
+    Suspended,
+    /// A request of the configuration channel waits in [`Usb::config_request`]
+    ConfigRequest,
+    /// A console line waits, it runs here as its commands use the engine
+    /// state. Also looked for on every other event, in case the queue was
+    /// full
+    #[cfg(feature = "console")]
+    ConsoleLine,
+}
+
+/// Reports for the USB task, each one is resent until the endpoint takes it
+pub enum UsbReport {
+    Keyboard(FixedVec<Keyboard, 58>),
+    Consumer(MultipleConsumerReport),
+    System(SystemControl),
+    Mouse(WheelMouseReport),
+    /// Response of the configuration channel, the action follows once the
+    /// host has it
+    Config(Report, Action),
+}
+
+pub struct Usb {
+    device: UsbDevice<'static, Bus>,
+    hid: Hid,
+    #[cfg(feature = "console")]
+    console: console::Console<'static, Bus>,
+    /// Set by the USB interrupt, a flag as an edge sent alone could be lost
+    suspended: bool,
+    /// Taken by the keymap task. The next request stays in the endpoint,
+    /// which holds the host off, until this one is taken
+    config_request: Option<Report>,
+}
+
+#[rtic::app(device = stm32f1xx_hal::pac, peripherals = false, dispatchers = [EXTI0])]
+mod app {
+    use super::*;
+
+    #[shared]
+    struct Shared {
+        usb: Usb,
+        counters: DiagnosticCounters,
+    }
+
+    #[local]
+    struct Local {
+        matrix: Matrix<<Target as Board>::Column, <Target as Board>::Row>,
+        delay: <Target as Board>::Delay,
+        link_tx: <Target as Board>::LinkTx,
+        link_rx: <Target as Board>::LinkRx,
+        /// Shows Caps Lock
+        caps_lock_led: Option<Led<<Target as Board>::LedPin>>,
+        flash: <Target as HostBoard>::Flash,
+        eeprom: Eeprom,
+        settings: Settings,
+        link_events: Sender<'static, Event, QUEUE_LEN>,
+        usb_events: Sender<'static, Event, QUEUE_LEN>,
+    }
+
+    #[init(local = [
+        usb_bus: Option<UsbBusAllocator<Bus>> = None,
+        serial_number: [u8; usb_identity::SERIAL_NUMBER_LEN] = [0; usb_identity::SERIAL_NUMBER_LEN],
+    ])]
+    fn init(cx: init::Context) -> (Shared, Local) {
+        let (parts, host) = Target::init_host();
+        Mono::start(cx.core.SYST, Target::SYSCLK_HZ);
+        let Parts {
+            mut matrix,
+            link_tx,
+            link_rx,
+            mut delay,
+            led: mut caps_lock_led,
+        } = parts;
+        let HostParts { usb_bus, mut flash } = host;
+        let usb_bus = cx.local.usb_bus.insert(usb_bus);
+
+        let hid = UsbHidClassBuilder::new()
+            .add_device(NKROBootKeyboardConfig::default())
+            .add_device(ConsumerControlConfig::default())
+            .add_device(WheelMouseConfig::default())
+            .add_device(SystemControlConfig::default())
+            .add_device(RawHidConfig::default())
+            .build(usb_bus);
+
+        #[cfg(feature = "console")]
+        let console = console::Console::new(usb_bus);
+
+        let usb_dev_builder =
+            UsbDeviceBuilder::new(usb_bus, UsbVidPid(usb_identity::VID, usb_identity::PID))
+                .strings(&[StringDescriptors::default()
+                    .manufacturer(usb_identity::MANUFACTURER)
+                    .product(usb_identity::PRODUCT)
+                    .serial_number(usb_identity::serial_number(
+                        Target::unique_id(),
+                        cx.local.serial_number,
+                    ))])
+                .unwrap_or_else(|_| panic!())
+                .supports_remote_wakeup(true);
+        // CDC-ACM spans two interfaces, hosts need an IAD to bind them together
+        #[cfg(feature = "console")]
+        let usb_dev_builder = usb_dev_builder.composite_with_iads();
+        let device = usb_dev_builder.build();
+
+        // Bootmagic: the key held while plugging in clears the stored keymap
+        // and settings, acknowledged by a blink of the LED
+        if matrix.is_pressed(CLEAR_CONFIG_KEY, &mut delay) {
+            let _ = storage::factory_reset(&mut flash);
+            if let Some(led) = &mut caps_lock_led {
+                led.set(true);
+                delay.delay_ms(200);
+                led.set(false);
+            }
+        }
+
+        let eeprom = storage::open(&mut flash).unwrap_or_else(|_| panic!());
+        let settings = Settings::load(&eeprom, &mut flash);
+        keymap_storage::load(&mut flash);
+
+        let (event_sender, events) = make_channel!(Event, QUEUE_LEN);
+        let (report_sender, reports) = make_channel!(UsbReport, QUEUE_LEN);
+        let (link_sender, link_bytes) = make_channel!(u8, QUEUE_LEN);
+
+        scan::spawn(event_sender.clone()).unwrap_or_else(|_| panic!());
+        keymap::spawn(events, report_sender, link_sender.clone()).unwrap_or_else(|_| panic!());
+        usb_write::spawn(reports, link_sender).unwrap_or_else(|_| panic!());
+        hid_tick::spawn().unwrap_or_else(|_| panic!());
+        link_transmit::spawn(link_bytes).unwrap_or_else(|_| panic!());
+
+        let usb = Usb {
+            device,
+            hid,
+            #[cfg(feature = "console")]
+            console,
+            suspended: false,
+            config_request: None,
+        };
+        let shared = Shared {
+            usb,
+            counters: DiagnosticCounters::default(),
+        };
+        let local = Local {
+            matrix,
+            delay,
+            link_tx,
+            link_rx,
+            caps_lock_led,
+            flash,
+            eeprom,
+            settings,
+            link_events: event_sender.clone(),
+            usb_events: event_sender,
+        };
+        (shared, local)
+    }
+
+    #[task(priority = 1, shared = [usb], local = [matrix, delay])]
+    async fn scan(mut cx: scan::Context, mut events: Sender<'static, Event, QUEUE_LEN>) {
+        let mut next = Mono::now();
+        loop {
+            let left_matrix = PrimitiveBitset::new(cx.local.matrix.scan(cx.local.delay));
+            let _ = events.send(Event::Left(left_matrix)).await;
+
+            // Low-power mode while the host sleeps: scan rarely, still fast
+            // enough for a keypress to wake it up
+            let suspended = cx.shared.usb.lock(|usb| usb.suspended);
+            let period = if suspended { 10.millis() } else { 1.millis() };
+            // A scan held up by a busy keymap task isn't caught up on
+            next = Mono::now().max(next + period);
+            Mono::delay_until(next).await;
+        }
+    }
+
+    #[task(
+        binds = USART3,
+        priority = 3,
+        local = [
+            link_rx,
+            link_events,
+            packet: [u8; MATRIX_PACKET_LEN] = [0; MATRIX_PACKET_LEN],
+            packet_len: usize = 0,
+        ]
+    )]
+    fn link_receive(cx: link_receive::Context) {
+        let link_receive::LocalResources {
+            link_rx,
+            link_events,
+            packet,
+            packet_len,
+            ..
+        } = cx.local;
+
+        // A full queue drops the matrix, the next one follows within a
+        // millisecond
+        while let Ok(received) = link_rx.read() {
+            // Message start
+            if received & 0x80 == 0x80 {
+                // Buffer is truly start
+                if *packet_len == 0 {
+                    packet[0] = received;
+                    *packet_len = 1;
+                } else {
+                    // Buffer is corrupted
+                    *packet_len = 0;
+                    let _ = link_events.try_send(Event::LinkError);
+                }
+            } else if *packet_len != 0 {
+                packet[*packet_len] = received;
+                *packet_len += 1;
+            }
+
+            if *packet_len == MATRIX_PACKET_LEN {
+                *packet_len = 0;
+                let data = MatrixBitset::unpack_7bit(packet);
+                let right_matrix = PrimitiveBitset::new(data.words()[0]);
+                let _ = link_events.try_send(Event::Right(right_matrix));
+            }
+        }
+    }
+
+    #[task(priority = 1, local = [link_tx])]
+    async fn link_transmit(
+        cx: link_transmit::Context,
+        mut bytes: Receiver<'static, u8, QUEUE_LEN>,
+    ) {
+        while let Ok(byte) = bytes.recv().await {
+            // A byte takes ~170 us at 57600 baud, less than a tick of the
+            // time base
+            let _ = nb::block!(cx.local.link_tx.write(byte));
+        }
+    }
+
+    #[task(priority = 1, shared = [usb, counters], local = [caps_lock_led, flash, eeprom, settings])]
+    async fn keymap(
+        mut cx: keymap::Context,
+        mut events: Receiver<'static, Event, QUEUE_LEN>,
+        mut reports: Sender<'static, UsbReport, QUEUE_LEN>,
+        mut link: Sender<'static, u8, QUEUE_LEN>,
+    ) {
+        let keymap::LocalResources {
+            caps_lock_led,
+            flash,
+            eeprom,
+            settings,
+            ..
+        } = cx.local;
+
+        let mut left_matrix = PrimitiveBitset::new(0u32);
+        let mut right_matrix = PrimitiveBitset::new(0u32);
+        let mut key_report: FixedVec<_, 58> = FixedVec::new(Keyboard::NoEventIndicated);
+        let mut media_report: FixedVec<_, 4> = FixedVec::new(Consumer::Unassigned);
+        let mut system_report = SystemControl::None;
+        let mut mouse_keys = MouseKeys::new();
+
+        let mut status = MainHalfStatus::default();
+        let _ = link.send(status.pack()).await;
+        let mut status_sent = Mono::now();
+        let mut wakeup_requested = false;
+        // Time of the last keymap edit not stored yet
+        let mut keymap_edited = None;
+
+        while let Ok(event) = events.recv().await {
+            // Reboot requests from keys or the console
+            let mut action = Action::None;
+            // Reports wait for the resume while the host sleeps
+            let mut send_reports = false;
+            let mut status_changed = false;
+
+            let suspended = cx.shared.usb.lock(|usb| usb.suspended);
+            if suspended != status.suspended {
+                status.suspended = suspended;
+                status_changed = true;
+                wakeup_requested = false;
+                // The host has the state from before it slept
+                send_reports = !suspended;
+            }
+
+            match event {
+                Event::Left(matrix) => {
+                    left_matrix = matrix;
+
+                    // A BIOS selects the boot protocol with SET_PROTOCOL, boot
+                    // reports can't hold more keys either
+                    let boot_protocol = cx.shared.usb.lock(|usb| {
+                        usb.hid
+                            .device::<NKROBootKeyboard<'_, _>, _>()
+                            .interface()
+                            .get_protocol()
+                            == HidProtocol::Boot
+                    });
+                    let mode = if boot_protocol {
+                        KeyboardMode::SixKeyRollover
                     } else {
-                        // Buffer is corrupted
-                        uart_buffer_len = 0;
+                        settings.keyboard_mode
+                    };
+
+                    if layouts_def::get_report(
+                        left_matrix,
+                        right_matrix,
+                        status.leds,
+                        mode,
+                        &mut key_report,
+                        &mut media_report,
+                        &mut system_report,
+                        &mut mouse_keys,
+                    ) {
+                        send_reports = !status.suspended;
+                        #[cfg(feature = "console")]
+                        cx.shared.usb.lock(|usb| {
+                            usb.console.log(format_args!(
+                                "keys: left {:#010x}, right {:#010x}",
+                                left_matrix.get_raw(),
+                                right_matrix.get_raw()
+                            ))
+                        });
+
+                        // A keypress wakes up the host if it allowed that
+                        let pressed = !left_matrix.is_empty() || !right_matrix.is_empty();
+                        if status.suspended
+                            && pressed
+                            && !wakeup_requested
+                            && cx.shared.usb.lock(|usb| usb.device.remote_wakeup_enabled())
+                        {
+                            wakeup_requested = true;
+                            // The USB interrupt stays off the registers meanwhile
+                            cx.shared.usb.lock(|_| Target::signal_resume(true));
+                            Mono::delay(10.millis()).await;
+                            cx.shared.usb.lock(|_| Target::signal_resume(false));
+                        }
                     }
-                } else {
-                    if uart_buffer_len != 0 {
-                        uart_buffer[uart_buffer_len as usize] = received;
-                        uart_buffer_len += 1;
+
+                    match layouts_def::take_command() {
+                        Some(KeyCommand::ToggleKeyboardMode) => {
+                            settings.keyboard_mode = match settings.keyboard_mode {
+                                KeyboardMode::Nkro => KeyboardMode::SixKeyRollover,
+                                KeyboardMode::SixKeyRollover => KeyboardMode::Nkro,
+                            };
+                            settings.store(eeprom, flash);
+                        }
+                        Some(KeyCommand::EnterBootloader) => action = Action::RebootToBootloader,
+                        Some(KeyCommand::EnterRightBootloader) => {
+                            action = Action::RightHalfBootloader
+                        }
+                        None => {}
+                    }
+
+                    if let Some(report) = mouse_keys.tick() {
+                        if !status.suspended {
+                            let _ = reports.send(UsbReport::Mouse(report)).await;
+                        }
+                    }
+
+                    // Scans slow down while the host sleeps, the time comes
+                    // from the time base
+                    let now = Mono::now();
+                    if now - status_sent >= STATUS_RESEND_MS.millis() {
+                        status_changed = true;
                     }
+
+                    // Edits come in bursts (VIA sends a key per request), the
+                    // flash page is rewritten once they stop
+                    if layouts_def::take_keymap_changed() {
+                        keymap_edited = Some(now);
+                    }
+                    if let Some(edited) = keymap_edited {
+                        if now - edited >= KEYMAP_SAVE_DELAY_MS.millis() {
+                            keymap_edited = None;
+                            keymap_storage::store(flash);
+                            #[cfg(feature = "console")]
+                            cx.shared
+                                .usb
+                                .lock(|usb| usb.console.log(format_args!("keymap: stored")));
+                        }
+                    }
+                }
+                Event::Right(matrix) => {
+                    right_matrix = matrix;
+                    cx.shared.counters.lock(|counters| {
+                        counters.link_packets = counters.link_packets.wrapping_add(1)
+                    });
+                }
+                Event::LinkError => {
+                    cx.shared.counters.lock(|counters| {
+                        counters.link_errors = counters.link_errors.wrapping_add(1)
+                    });
+                    #[cfg(feature = "console")]
+                    cx.shared.usb.lock(|usb| {
+                        usb.console
+                            .log(format_args!("link: broken message dropped"))
+                    });
+                }
+                Event::HostLeds(leds) => {
+                    status_changed = leds != status.leds;
+                    status.leds = leds;
+                }
+                Event::Suspended => {}
+                Event::ConfigRequest => {}
+                #[cfg(feature = "console")]
+                Event::ConsoleLine => {}
+            }
+
+            // Also looked for on every other event, in case the queue was full
+            if let Some(request) = cx.shared.usb.lock(|usb| usb.config_request.take()) {
+                // The interrupt reads the next request, which waits in the
+                // endpoint
+                rtic::pend(stm32f1xx_hal::pac::Interrupt::USB_LP_CAN_RX0);
+                let counters = cx.shared.counters.lock(|counters| *counters);
+                let uptime_ms = Mono::now().duration_since_epoch().to_millis();
+                let mut response = [0u8; REPORT_LEN];
+                let action =
+                    config_channel::handle_request(&request, &counters, uptime_ms, &mut response);
+                let _ = reports.send(UsbReport::Config(response, action)).await;
+            }
+
+            #[cfg(feature = "console")]
+            {
+                let counters = cx.shared.counters.lock(|counters| *counters);
+                let console_action = cx.shared.usb.lock(|usb| usb.console.run(&counters));
+                if let Some(console_action) = console_action {
+                    action = console_action;
                 }
             }
-            Err(_) => {}
+
+            if send_reports {
+                let mut codes = [Consumer::Unassigned; 4];
+                codes[..media_report.len()].copy_from_slice(&media_report);
+                for report in [
+                    UsbReport::Keyboard(key_report),
+                    UsbReport::Consumer(MultipleConsumerReport { codes }),
+                    UsbReport::System(system_report),
+                ] {
+                    let _ = reports.send(report).await;
+                }
+            }
+
+            // Forward the LED and suspend state to the right half
+            if status_changed {
+                status_sent = Mono::now();
+                let _ = link.send(status.pack()).await;
+            }
+
+            perform(action, &mut link).await;
+
+            // LEDs are off while the host sleeps
+            if let Some(led) = caps_lock_led.as_mut() {
+                led.set(status.leds.get(HostLeds::CAPS_LOCK) && !status.suspended);
+            }
         }
+    }
 
-        // If buffer filled
-        if uart_buffer_len == 5 {
-            uart_buffer_len = 0;
-            let data = unpack_keydata(uart_buffer);
-            let right_matrix = PrimitiveBitset::new(data);
-
-            // Read left matrix
-            for (r, pw) in power_pins.iter_mut().enumerate() {
-                pw.set_high();
-                delay(10);
-                for (c, sg) in signal_pins.iter().enumerate() {
-                    left_matrix[r * signal_pins.len() + c] = sg.is_high();
+    /// On the low priority interrupt, which gets all events of the device
+    #[task(binds = USB_LP_CAN_RX0, priority = 2, shared = [usb], local = [usb_events])]
+    fn usb_poll(mut cx: usb_poll::Context) {
+        let usb_events = cx.local.usb_events;
+
+        cx.shared.usb.lock(|usb| {
+            if usb.device.poll(&mut [
+                &mut usb.hid,
+                #[cfg(feature = "console")]
+                usb.console.serial(),
+            ]) {
+                if let Ok(report) = usb.hid.device::<NKROBootKeyboard<'_, _>, _>().read_report() {
+                    let _ = usb_events.try_send(Event::HostLeds(host_leds(&report)));
                 }
-                pw.set_low();
-            }
-
-            layouts_def::get_report(
-                &left_matrix,
-                right_matrix,
-                &mut key_report,
-                &mut media_report,
-            );
-
-            keyboard
-                .device::<NKROBootKeyboard<'_, _>, _>()
-                .write_report(key_report.data)
-                .ok();
-            keyboard
-                .device::<ConsumerControl<'_, _>, _>()
-                .write_report(&MultipleConsumerReport {
-                    codes: media_report.data,
-                })
-                .ok();
+            }
+
+            // Also read when the keymap task pended the interrupt for it
+            if usb.config_request.is_none() {
+                let mut request = [0u8; REPORT_LEN];
+                if usb
+                    .hid
+                    .device::<RawHid<'_, _>, _>()
+                    .read_report(&mut request)
+                    .is_ok()
+                {
+                    usb.config_request = Some(request);
+                    let _ = usb_events.try_send(Event::ConfigRequest);
+                }
+            }
+
+            let now_suspended = usb.device.state() == UsbDeviceState::Suspend;
+            if now_suspended != usb.suspended {
+                usb.suspended = now_suspended;
+                let _ = usb_events.try_send(Event::Suspended);
+                #[cfg(feature = "console")]
+                usb.console
+                    .log(format_args!("usb: suspended {now_suspended}"));
+            }
+
+            #[cfg(feature = "console")]
+            if usb.console.poll() {
+                let _ = usb_events.try_send(Event::ConsoleLine);
+            }
+        });
+    }
+
+    #[task(priority = 1, shared = [usb, counters])]
+    async fn usb_write(
+        mut cx: usb_write::Context,
+        mut reports: Receiver<'static, UsbReport, QUEUE_LEN>,
+        mut link: Sender<'static, u8, QUEUE_LEN>,
+    ) {
+        while let Ok(report) = reports.recv().await {
+            loop {
+                let written = cx.shared.usb.lock(|usb| match &report {
+                    UsbReport::Keyboard(keys) => usb
+                        .hid
+                        .device::<NKROBootKeyboard<'_, _>, _>()
+                        .write_report(keys.iter().copied()),
+                    UsbReport::Consumer(report) => usb
+                        .hid
+                        .device::<ConsumerControl<'_, _>, _>()
+                        .write_report(report),
+                    UsbReport::System(report) => usb
+                        .hid
+                        .device::<SystemControlDevice<'_, _>, _>()
+                        .write_report(*report),
+                    UsbReport::Mouse(report) => usb
+                        .hid
+                        .device::<WheelMouse<'_, _>, _>()
+                        .write_report(report),
+                    UsbReport::Config(response, _) => {
+                        usb.hid.device::<RawHid<'_, _>, _>().write_report(response)
+                    }
+                });
+
+                let keyboard = matches!(report, UsbReport::Keyboard(_));
+                match written {
+                    // The endpoint is free again once the host polled it
+                    Err(UsbHidError::WouldBlock) => {
+                        if keyboard {
+                            cx.shared.counters.lock(|counters| {
+                                counters.report_retries = counters.report_retries.wrapping_add(1)
+                            });
+                        }
+                        Mono::delay(1.millis()).await;
+                    }
+                    Ok(()) => {
+                        if keyboard {
+                            cx.shared.counters.lock(|counters| {
+                                counters.reports_sent = counters.reports_sent.wrapping_add(1)
+                            });
+                        }
+                        break;
+                    }
+                    Err(_) => break,
+                }
+            }
+
+            if let UsbReport::Config(_, action) = report {
+                perform(action, &mut link).await;
+            }
         }
+    }
 
-        if timer.wait().is_ok() {
-            keyboard.tick().unwrap_or_else(|_| panic!());
+    /// Idle reports and the timing of the HID class
+    #[task(priority = 1, shared = [usb])]
+    async fn hid_tick(mut cx: hid_tick::Context) {
+        loop {
+            cx.shared
+                .usb
+                .lock(|usb| usb.hid.tick())
+                .unwrap_or_else(|_| panic!());
+            Mono::delay(1.millis()).await;
         }
+    }
+}
 
-        if usb_dev.poll(&mut [&mut keyboard]) {
-            let _ = keyboard.device::<NKROBootKeyboard<'_, _>, _>().read_report();
+/// Carries out a reboot request
+async fn perform(action: Action, link: &mut Sender<'static, u8, QUEUE_LEN>) {
+    match action {
+        Action::None => {}
+        Action::RebootToBootloader => {
+            // Give the host time to fetch the response
+            Mono::delay(20.millis()).await;
+            Target::reboot_to_bootloader();
+        }
+        Action::RightHalfBootloader => {
+            let _ = link.send(MainHalfCommand::EnterBootloader.pack()).await;
         }
     }
 }
 
-/// Byte format:
-///  - [0] bit:
-///     - 1: It's the message first byte
-///     - 0: It's the message continuation byte \[1..5\]
-///  -   [1..7] bits: data
-fn unpack_keydata(data: [u8; 5]) -> u32 {
-    let b0 = (data[0] & 0x7f) as u32;
-    let b1 = (data[1] & 0x7f) as u32;
-    let b2 = (data[2] & 0x7f) as u32;
-    let b3 = (data[3] & 0x7f) as u32;
-    let b4 = (data[4] & 0x7f) as u32;
-
-    let le = b0 | (b1 << 7) | (b2 << 14) | (b3 << 21) | (b4 << 28);
-
-    u32::from_le(le)
+fn host_leds(report: &KeyboardLedsReport) -> HostLeds {
+    let mut leds = 0;
+    for (lit, led) in [
+        (report.num_lock, HostLeds::NUM_LOCK),
+        (report.caps_lock, HostLeds::CAPS_LOCK),
+        (report.scroll_lock, HostLeds::SCROLL_LOCK),
+        (report.compose, HostLeds::COMPOSE),
+        (report.kana, HostLeds::KANA),
+    ] {
+        if lit {
+            leds |= led;
+        }
+    }
+    HostLeds(leds)
 }
@@ -0,0 +1,97 @@
+//! Vendor-defined raw HID device carrying the configuration channel, see
+//! `shared_src::config_protocol`. Usage page and usages match QMK's raw HID
+//! so existing host libraries can find the interface
+
+use fugit::ExtU32;
+use shared_src::config_protocol::{Report, REPORT_LEN};
+use usb_device::bus::{UsbBus, UsbBusAllocator};
+use usbd_human_interface_device::device::DeviceClass;
+use usbd_human_interface_device::interface::{
+    InBytes64, Interface, InterfaceBuilder, InterfaceConfig, OutBytes64, ReportSingle,
+    UsbAllocatable,
+};
+use usbd_human_interface_device::UsbHidError;
+
+#[rustfmt::skip]
+const RAW_HID_DESCRIPTOR: &[u8] = &[
+    0x06, 0x60, 0xFF, // Usage Page (Vendor Defined 0xFF60)
+    0x09, 0x61,       // Usage (0x61)
+    0xA1, 0x01,       // Collection (Application)
+    0x09, 0x62,       //   Usage (0x62)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x95, 0x40,       //   Report Count (64)
+    0x75, 0x08,       //   Report Size (8)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute)
+    0x09, 0x63,       //   Usage (0x63)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x95, 0x40,       //   Report Count (64)
+    0x75, 0x08,       //   Report Size (8)
+    0x91, 0x02,       //   Output (Data, Variable, Absolute)
+    0xC0,             // End Collection
+];
+
+pub struct RawHid<'a, B: UsbBus> {
+    interface: Interface<'a, B, InBytes64, OutBytes64, ReportSingle>,
+}
+
+impl<B: UsbBus> RawHid<'_, B> {
+    /// Reads a request sent by the host, `WouldBlock` if there is none
+    pub fn read_report(&mut self, report: &mut Report) -> Result<(), UsbHidError> {
+        match self.interface.read_report(report) {
+            Ok(REPORT_LEN) => Ok(()),
+            Ok(_) => Err(UsbHidError::SerializationError),
+            Err(e) => Err(UsbHidError::from(e)),
+        }
+    }
+
+    pub fn write_report(&mut self, report: &Report) -> Result<(), UsbHidError> {
+        self.interface
+            .write_report(report)
+            .map(|_| ())
+            .map_err(UsbHidError::from)
+    }
+}
+
+impl<'a, B: UsbBus> DeviceClass<'a> for RawHid<'a, B> {
+    type I = Interface<'a, B, InBytes64, OutBytes64, ReportSingle>;
+
+    fn interface(&mut self) -> &mut Self::I {
+        &mut self.interface
+    }
+
+    fn reset(&mut self) {}
+
+    fn tick(&mut self) -> Result<(), UsbHidError> {
+        Ok(())
+    }
+}
+
+pub struct RawHidConfig<'a> {
+    interface: InterfaceConfig<'a, InBytes64, OutBytes64, ReportSingle>,
+}
+
+impl Default for RawHidConfig<'_> {
+    fn default() -> Self {
+        let interface = InterfaceBuilder::new(RAW_HID_DESCRIPTOR)
+            .unwrap_or_else(|_| panic!())
+            .description("Configuration")
+            .in_endpoint(1.millis())
+            .unwrap_or_else(|_| panic!())
+            .with_out_endpoint(1.millis())
+            .unwrap_or_else(|_| panic!())
+            .build();
+        Self { interface }
+    }
+}
+
+impl<'a, B: UsbBus + 'a> UsbAllocatable<'a, B> for RawHidConfig<'a> {
+    type Allocated = RawHid<'a, B>;
+
+    fn allocate(self, usb_alloc: &'a UsbBusAllocator<B>) -> Self::Allocated {
+        RawHid {
+            interface: Interface::new(usb_alloc, self.interface),
+        }
+    }
+}
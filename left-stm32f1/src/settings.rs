@@ -0,0 +1,39 @@
+//! Settings kept across power cycles in the EEPROM emulation of
+//! [`shared_src::eeprom`], on the last two flash pages which `memory.x` leaves
+//! out of the application region. [`shared_src::storage`] opens it and
+//! checks its version.
+//!
+//! Every setting has a key of its own, a missing or unknown value loads the
+//! default of that setting.
+
+use embedded_storage::nor_flash::NorFlash;
+use keyboard_core::layouts_def::KeyboardMode;
+use shared_src::eeprom::Eeprom;
+use shared_src::storage::keys;
+
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub struct Settings {
+    pub keyboard_mode: KeyboardMode,
+}
+
+impl Settings {
+    pub fn load(eeprom: &Eeprom, flash: &mut impl NorFlash) -> Self {
+        let mut value = [0; 1];
+        let keyboard_mode = match eeprom.read(flash, keys::KEYBOARD_MODE, &mut value) {
+            Ok(Some(1)) if value[0] == 1 => KeyboardMode::SixKeyRollover,
+            _ => KeyboardMode::Nkro,
+        };
+        Self { keyboard_mode }
+    }
+
+    /// Appends the changed values, the CPU stalls for a few ms meanwhile and
+    /// ~40 ms when the pages get compacted
+    pub fn store(&self, eeprom: &mut Eeprom, flash: &mut impl NorFlash) {
+        let mode = match self.keyboard_mode {
+            KeyboardMode::Nkro => 0u8,
+            KeyboardMode::SixKeyRollover => 1,
+        };
+        // A failed write keeps the previous value
+        let _ = eeprom.write(flash, keys::KEYBOARD_MODE, &[mode]);
+    }
+}
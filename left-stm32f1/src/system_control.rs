@@ -0,0 +1,91 @@
+//! Generic Desktop System Control device (power down, sleep, wake up).
+//! `usbd-human-interface-device` has no such device, so it is built the same
+//! way as the crate's `ConsumerControl`
+
+use fugit::ExtU32;
+use usb_device::bus::{UsbBus, UsbBusAllocator};
+use usbd_human_interface_device::device::DeviceClass;
+use usbd_human_interface_device::interface::{
+    InBytes8, Interface, InterfaceBuilder, InterfaceConfig, OutNone, ReportSingle, UsbAllocatable,
+};
+use usbd_human_interface_device::UsbHidError;
+
+pub use keyboard_core::system_control::SystemControl;
+
+#[rustfmt::skip]
+const SYSTEM_CONTROL_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x80, // Usage (System Control)
+    0xA1, 0x01, // Collection (Application)
+    0x19, 0x81, //   Usage Minimum (System Power Down)
+    0x29, 0x83, //   Usage Maximum (System Wake Up)
+    0x15, 0x01, //   Logical Minimum (1)
+    0x25, 0x03, //   Logical Maximum (3)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x00, //   Input (Data, Array, Absolute)
+    0xC0,       // End Collection
+];
+
+pub struct SystemControlDevice<'a, B: UsbBus> {
+    interface: Interface<'a, B, InBytes8, OutNone, ReportSingle>,
+    last_report: Option<SystemControl>,
+}
+
+impl<B: UsbBus> SystemControlDevice<'_, B> {
+    pub fn write_report(&mut self, report: SystemControl) -> Result<(), UsbHidError> {
+        if self.last_report == Some(report) {
+            return Err(UsbHidError::Duplicate);
+        }
+
+        self.interface
+            .write_report(&[report as u8])
+            .map_err(UsbHidError::from)?;
+        self.last_report = Some(report);
+        Ok(())
+    }
+}
+
+impl<'a, B: UsbBus> DeviceClass<'a> for SystemControlDevice<'a, B> {
+    type I = Interface<'a, B, InBytes8, OutNone, ReportSingle>;
+
+    fn interface(&mut self) -> &mut Self::I {
+        &mut self.interface
+    }
+
+    fn reset(&mut self) {
+        self.last_report = None;
+    }
+
+    fn tick(&mut self) -> Result<(), UsbHidError> {
+        Ok(())
+    }
+}
+
+pub struct SystemControlConfig<'a> {
+    interface: InterfaceConfig<'a, InBytes8, OutNone, ReportSingle>,
+}
+
+impl Default for SystemControlConfig<'_> {
+    fn default() -> Self {
+        let interface = InterfaceBuilder::new(SYSTEM_CONTROL_DESCRIPTOR)
+            .unwrap_or_else(|_| panic!())
+            .description("System Control")
+            .in_endpoint(10.millis())
+            .unwrap_or_else(|_| panic!())
+            .without_out_endpoint()
+            .build();
+        Self { interface }
+    }
+}
+
+impl<'a, B: UsbBus + 'a> UsbAllocatable<'a, B> for SystemControlConfig<'a> {
+    type Allocated = SystemControlDevice<'a, B>;
+
+    fn allocate(self, usb_alloc: &'a UsbBusAllocator<B>) -> Self::Allocated {
+        SystemControlDevice {
+            interface: Interface::new(usb_alloc, self.interface),
+            last_report: None,
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! USB identity of the keyboard. The defaults can be overridden at build
+//! time through the `USB_VID`, `USB_PID` (hex, `0x` prefix optional),
+//! `USB_MANUFACTURER` and `USB_PRODUCT` environment variables.
+//!
+//! The serial number is the chip's unique ID, so several boards plugged into
+//! the same host can be told apart.
+
+/// pid.codes open source vendor ID
+pub const VID: u16 = match option_env!("USB_VID") {
+    Some(vid) => parse_id(vid),
+    None => 0x1209,
+};
+
+/// pid.codes test PID, private builds only. Released builds should get their
+/// own PID from pid.codes and pass it in `USB_PID`
+pub const PID: u16 = match option_env!("USB_PID") {
+    Some(pid) => parse_id(pid),
+    None => 0x0001,
+};
+
+pub const MANUFACTURER: &str = match option_env!("USB_MANUFACTURER") {
+    Some(manufacturer) => manufacturer,
+    None => "VirhPotujnosti",
+};
+
+pub const PRODUCT: &str = match option_env!("USB_PRODUCT") {
+    Some(product) => product,
+    None => "VirhPotujnosti Split Keyboard",
+};
+
+/// Longest unique ID the serial number holds, the STM32 ones have 12 bytes
+const MAX_ID_LEN: usize = 16;
+
+pub const SERIAL_NUMBER_LEN: usize = MAX_ID_LEN * 2;
+
+/// Formats the chip's unique ID as upper case hex into `buffer`
+pub fn serial_number<'a>(unique_id: &[u8], buffer: &'a mut [u8; SERIAL_NUMBER_LEN]) -> &'a str {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+    let unique_id = &unique_id[..unique_id.len().min(MAX_ID_LEN)];
+    for (i, byte) in unique_id.iter().enumerate() {
+        buffer[i * 2] = DIGITS[(byte >> 4) as usize];
+        buffer[i * 2 + 1] = DIGITS[(byte & 0x0F) as usize];
+    }
+    core::str::from_utf8(&buffer[..unique_id.len() * 2]).unwrap_or_else(|_| panic!())
+}
+
+/// Fails the build on an invalid override
+const fn parse_id(text: &str) -> u16 {
+    let bytes = text.as_bytes();
+    let mut i = if bytes.len() > 2 && bytes[0] == b'0' && (bytes[1] == b'x' || bytes[1] == b'X') {
+        2
+    } else {
+        0
+    };
+    assert!(
+        i < bytes.len() && bytes.len() - i <= 4,
+        "USB ID must have 1 to 4 hex digits"
+    );
+
+    let mut id = 0u16;
+    while i < bytes.len() {
+        let digit = match bytes[i] {
+            b @ b'0'..=b'9' => b - b'0',
+            b @ b'a'..=b'f' => b - b'a' + 10,
+            b @ b'A'..=b'F' => b - b'A' + 10,
+            _ => panic!("USB ID must be hexadecimal"),
+        };
+        id = id << 4 | digit as u16;
+        i += 1;
+    }
+    id
+}
@@ -0,0 +1,183 @@
+//! VIA protocol handler, lets the VIA/Vial GUI edit the keymap.
+//!
+//! VIA sees a single 10x6 matrix: rows 0-4 are the left half and rows 5-9
+//! the right half, both in the order of `KEYBOARD_LAYOUT`. It shows
+//! `LAYER_COUNT` layers, the left half has fewer of them and reads `KC_NO`
+//! there. Keycodes VIA can't express (or we can't map) also read `KC_NO`,
+//! so a write of the keycode a key already reads is skipped: saving the
+//! whole keymap back keeps them. Macros aren't supported, VIA is told there
+//! are none.
+
+use crate::config_channel::{Action, FIRMWARE_VERSION};
+use keyboard_core::layouts_def::{self, MultiKey};
+use shared_src::config_protocol::{KeyPosition, Report};
+use shared_src::via;
+
+const ROWS: u8 = 10;
+const COLS: u8 = 6;
+const ROWS_PER_SIDE: u8 = ROWS / 2;
+const LAYER_COUNT: u8 = 5;
+const KEYMAP_BYTES: usize = LAYER_COUNT as usize * ROWS as usize * COLS as usize * 2;
+
+static mut layout_options: u32 = 0;
+
+/// Handles `data` in place: VIA expects the request echoed back with the
+/// results written over the arguments
+pub fn handle_request(data: &mut Report, uptime_ms: u32) -> Action {
+    match data[0] {
+        via::GET_PROTOCOL_VERSION => {
+            data[1..3].copy_from_slice(&via::PROTOCOL_VERSION.to_be_bytes());
+        }
+        via::GET_KEYBOARD_VALUE => get_keyboard_value(data, uptime_ms),
+        via::SET_KEYBOARD_VALUE => set_keyboard_value(data),
+        via::DYNAMIC_KEYMAP_GET_KEYCODE => {
+            let keycode = get_keycode(data[1], data[2], data[3]);
+            data[4..6].copy_from_slice(&keycode.to_be_bytes());
+        }
+        via::DYNAMIC_KEYMAP_SET_KEYCODE => {
+            set_keycode(
+                data[1],
+                data[2],
+                data[3],
+                u16::from_be_bytes([data[4], data[5]]),
+            );
+        }
+        via::DYNAMIC_KEYMAP_RESET => layouts_def::reset_keymap(),
+        // No lighting to configure, accepted and ignored
+        via::CUSTOM_SET_VALUE | via::CUSTOM_SAVE => {}
+        via::CUSTOM_GET_VALUE => data[3..].fill(0),
+        via::EEPROM_RESET => {
+            layouts_def::reset_keymap();
+            unsafe { layout_options = 0 };
+        }
+        via::BOOTLOADER_JUMP => return Action::RebootToBootloader,
+        // No macros, the buffer is empty
+        via::DYNAMIC_KEYMAP_MACRO_GET_COUNT => data[1] = 0,
+        via::DYNAMIC_KEYMAP_MACRO_GET_BUFFER_SIZE => data[1..3].fill(0),
+        via::DYNAMIC_KEYMAP_MACRO_GET_BUFFER
+        | via::DYNAMIC_KEYMAP_MACRO_SET_BUFFER
+        | via::DYNAMIC_KEYMAP_MACRO_RESET => {}
+        via::DYNAMIC_KEYMAP_GET_LAYER_COUNT => data[1] = LAYER_COUNT,
+        via::DYNAMIC_KEYMAP_GET_BUFFER => {
+            let (offset, len) = buffer_range(data, KEYMAP_BYTES);
+            for i in 0..len {
+                let byte = offset + i;
+                let keycode = get_keycode_at(byte / 2).to_be_bytes();
+                data[4 + i] = keycode[byte % 2];
+            }
+        }
+        via::DYNAMIC_KEYMAP_SET_BUFFER => {
+            let (offset, len) = buffer_range(data, KEYMAP_BYTES);
+            let (first, last) = (offset / 2, (offset + len).div_ceil(2));
+            for entry in first..last {
+                // A chunk may start or end in the middle of a keycode
+                let mut keycode = get_keycode_at(entry).to_be_bytes();
+                for (half, byte) in keycode.iter_mut().enumerate() {
+                    let index = entry * 2 + half;
+                    if (offset..offset + len).contains(&index) {
+                        *byte = data[4 + index - offset];
+                    }
+                }
+                set_keycode_at(entry, u16::from_be_bytes(keycode));
+            }
+        }
+        _ => data[0] = via::UNHANDLED,
+    }
+    Action::None
+}
+
+fn get_keyboard_value(data: &mut Report, uptime_ms: u32) {
+    match data[1] {
+        via::UPTIME => data[2..6].copy_from_slice(&uptime_ms.to_be_bytes()),
+        via::LAYOUT_OPTIONS => {
+            data[2..6].copy_from_slice(&unsafe { layout_options }.to_be_bytes());
+        }
+        via::SWITCH_MATRIX_STATE => {
+            let (left, right) = layouts_def::matrix_state();
+            for row in 0..ROWS {
+                let (matrix, side_row) = if row < ROWS_PER_SIDE {
+                    (left.get_raw(), row)
+                } else {
+                    (right.get_raw(), row - ROWS_PER_SIDE)
+                };
+                data[2 + row as usize] = (matrix >> (side_row * COLS)) as u8 & ((1 << COLS) - 1);
+            }
+        }
+        via::FIRMWARE_VERSION => {
+            let version = FIRMWARE_VERSION;
+            data[2..6].copy_from_slice(&[0, version.major, version.minor, version.patch]);
+        }
+        _ => data[0] = via::UNHANDLED,
+    }
+}
+
+fn set_keyboard_value(data: &mut Report) {
+    match data[1] {
+        via::LAYOUT_OPTIONS => unsafe {
+            layout_options = u32::from_be_bytes([data[2], data[3], data[4], data[5]]);
+        },
+        // Asks the keyboard to blink, there is nothing to blink with
+        via::DEVICE_INDICATION => {}
+        _ => data[0] = via::UNHANDLED,
+    }
+}
+
+/// Start and length of a buffer command chunk, clamped to `buffer_len`
+fn buffer_range(data: &Report, buffer_len: usize) -> (usize, usize) {
+    let offset = (u16::from_be_bytes([data[1], data[2]]) as usize).min(buffer_len);
+    let len = (data[3] as usize)
+        .min(via::BUFFER_CHUNK_LEN)
+        .min(buffer_len - offset);
+    (offset, len)
+}
+
+fn position(layer: u8, row: u8, col: u8) -> Option<KeyPosition> {
+    if row >= ROWS || col >= COLS {
+        return None;
+    }
+
+    Some(KeyPosition {
+        side: row / ROWS_PER_SIDE,
+        layer,
+        index: (row % ROWS_PER_SIDE) * COLS + col,
+    })
+}
+
+fn get_keycode(layer: u8, row: u8, col: u8) -> u16 {
+    position(layer, row, col)
+        .and_then(|position| layouts_def::get_key(position).ok())
+        .map_or(0, |key| via::raw_to_keycode(key.to_raw()))
+}
+
+fn set_keycode(layer: u8, row: u8, col: u8, keycode: u16) {
+    // Unchanged, or a key VIA can't show written back as read
+    if keycode == get_keycode(layer, row, col) {
+        return;
+    }
+    let key = via::keycode_to_raw(keycode).and_then(MultiKey::from_raw);
+    if let (Some(position), Some(key)) = (position(layer, row, col), key) {
+        // Layers missing on the left half can't be written
+        let _ = layouts_def::set_key(position, key);
+    }
+}
+
+/// `entry` counts keycodes of the layer-major buffer of the buffer commands
+fn entry_position(entry: usize) -> (u8, u8, u8) {
+    let cells = ROWS as usize * COLS as usize;
+    let cell = entry % cells;
+    (
+        (entry / cells) as u8,
+        (cell / COLS as usize) as u8,
+        (cell % COLS as usize) as u8,
+    )
+}
+
+fn get_keycode_at(entry: usize) -> u16 {
+    let (layer, row, col) = entry_position(entry);
+    get_keycode(layer, row, col)
+}
+
+fn set_keycode_at(entry: usize, keycode: u16) {
+    let (layer, row, col) = entry_position(entry);
+    set_keycode(layer, row, col, keycode);
+}
@@ -0,0 +1,12 @@
+//! Puts memory.x where the linker finds it: cargo links from the workspace
+//! root, cortex-m-rt's link.x includes memory.x from the search path.
+
+use std::path::PathBuf;
+use std::{env, fs};
+
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::copy("memory.x", out_dir.join("memory.x")).unwrap();
+    println!("cargo:rustc-link-search={}", out_dir.display());
+    println!("cargo:rerun-if-changed=memory.x");
+}
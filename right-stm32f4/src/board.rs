@@ -0,0 +1,81 @@
+//! STM32F401 board of the right half: 25 MHz crystal, LED on PC13, UART to
+//! the main half on USART2 (PA2/PA3), matrix columns on PB9..PB5 and rows on
+//! PB10, PA5..PA7, PB0 and PB1.
+
+use crate::bootloader;
+use crate::hal::gpio::{ErasedPin, Input, Output};
+use crate::hal::serial::{Rx, Tx};
+use crate::hal::timer::DelayUs;
+use crate::hal::{pac, prelude::*};
+use keyboard_core::board::{Board, Led, Matrix, Parts};
+
+pub struct Stm32f401;
+
+impl Board for Stm32f401 {
+    type Column = ErasedPin<Output>;
+    type Row = ErasedPin<Input>;
+    type LedPin = ErasedPin<Output>;
+    type LinkTx = Tx<pac::USART2>;
+    type LinkRx = Rx<pac::USART2>;
+    type Delay = DelayUs<pac::TIM1>;
+
+    /// The crystal, without the PLL
+    const SYSCLK_HZ: u32 = 25_000_000;
+
+    fn init() -> Parts<Self> {
+        bootloader::jump_if_requested();
+
+        let dp = pac::Peripherals::take().unwrap();
+
+        let rcc = dp.RCC.constrain();
+        let clocks = rcc.cfgr.use_hse(Self::SYSCLK_HZ.Hz()).freeze();
+
+        let gpioa = dp.GPIOA.split();
+        let gpiob = dp.GPIOB.split();
+        let gpioc = dp.GPIOC.split();
+
+        // On-board LED, active low
+        let led = gpioc.pc13.into_push_pull_output().erase();
+
+        // RX receives the host LED state from the main half
+        let tx_pin = gpioa.pa2;
+        let rx_pin = gpioa.pa3;
+        let (link_tx, mut link_rx) = dp
+            .USART2
+            .serial((tx_pin, rx_pin), 57600.bps(), &clocks)
+            .unwrap()
+            .split();
+        link_rx.listen();
+
+        // Collumns
+        let power_pins = [
+            gpiob.pb9.into_push_pull_output().erase(),
+            gpiob.pb8.into_push_pull_output().erase(),
+            gpiob.pb7.into_push_pull_output().erase(),
+            gpiob.pb6.into_push_pull_output().erase(),
+            gpiob.pb5.into_push_pull_output().erase(),
+        ];
+
+        // Rows
+        let signal_pins = [
+            gpiob.pb10.into_pull_down_input().erase(),
+            gpioa.pa5.into_pull_down_input().erase(),
+            gpioa.pa6.into_pull_down_input().erase(),
+            gpioa.pa7.into_pull_down_input().erase(),
+            gpiob.pb0.into_pull_down_input().erase(),
+            gpiob.pb1.into_pull_down_input().erase(),
+        ];
+
+        Parts {
+            matrix: Matrix::new(power_pins, signal_pins),
+            link_tx,
+            link_rx,
+            delay: dp.TIM1.delay_us(&clocks),
+            led: Some(Led::new(led, true)),
+        }
+    }
+
+    fn reboot_to_bootloader() -> ! {
+        bootloader::reboot_to_bootloader()
+    }
+}
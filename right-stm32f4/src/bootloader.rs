@@ -0,0 +1,38 @@
+//! Reboot into the STM32F401 ROM bootloader, which enumerates as USB DFU on
+//! the board's USB port (e.g. `dfu-util -a 0 -s 0x08000000:leave -D ...`).
+//!
+//! The request survives the reset in a RAM word that cortex-m-rt leaves
+//! uninitialised. It is checked first thing in the RTIC `init`, before the
+//! clocks and the peripherals are configured: only the interrupt priorities
+//! RTIC sets up precede it, so the bootloader starts from a nearly clean
+//! reset state
+
+use core::mem::MaybeUninit;
+use cortex_m::peripheral::SCB;
+
+const SYSTEM_MEMORY: u32 = 0x1FFF_0000;
+const BOOTLOADER_MAGIC: u32 = 0xB007_10AD;
+
+#[link_section = ".uninit.BOOTLOADER_REQUEST"]
+static mut BOOTLOADER_REQUEST: MaybeUninit<u32> = MaybeUninit::uninit();
+
+pub fn reboot_to_bootloader() -> ! {
+    unsafe {
+        (*core::ptr::addr_of_mut!(BOOTLOADER_REQUEST))
+            .as_mut_ptr()
+            .write_volatile(BOOTLOADER_MAGIC);
+    }
+    SCB::sys_reset()
+}
+
+/// Must be called before the clocks or any peripheral are configured,
+/// `Board::init` does it first
+pub fn jump_if_requested() {
+    unsafe {
+        let request = (*core::ptr::addr_of_mut!(BOOTLOADER_REQUEST)).as_mut_ptr();
+        if request.read_volatile() == BOOTLOADER_MAGIC {
+            request.write_volatile(0);
+            cortex_m::asm::bootload(SYSTEM_MEMORY as *const u32);
+        }
+    }
+}
@@ -1,85 +1,133 @@
+//! Firmware of the right half. It runs as RTIC tasks:
+//!
+//! - `scan` scans the matrix every millisecond, every 10 ms while the host
+//!   sleeps
+//! - `link_transmit` sends the scans to the main half
+//! - `link_receive` (UART interrupt) takes the status and the commands of the
+//!   main half
+
 #![no_main]
 #![no_std]
 use panic_halt as _;
 
-use cortex_m_rt::entry;
-use nb::block;
+use embedded_hal_nb::serial::{Read, Write};
+use rtic_monotonics::systick::prelude::*;
+use rtic_sync::channel::{Receiver, Sender};
+use rtic_sync::make_channel;
 use stm32f4xx_hal::{self as hal};
-use crate::hal::{pac, prelude::*};
-
-use shared_src::PrimitiveBitset;
-
-
-#[entry]
-fn main() -> ! {
-    let dp = pac::Peripherals::take().unwrap();
-
-    let rcc = dp.RCC.constrain();
-    let clocks = rcc.cfgr.use_hse(25.MHz()).freeze();
-
-    let gpioa = dp.GPIOA.split();
-    let gpiob = dp.GPIOB.split();
-
-    let tx_pin = gpioa.pa2;
-    let mut tx = dp.USART2.tx(tx_pin, 57600.bps(), &clocks).unwrap();
-
-    // Collumns
-    let mut power_pins = [
-        gpiob.pb9.into_push_pull_output().erase(),
-        gpiob.pb8.into_push_pull_output().erase(),
-        gpiob.pb7.into_push_pull_output().erase(),
-        gpiob.pb6.into_push_pull_output().erase(),
-        gpiob.pb5.into_push_pull_output().erase(),
-    ];
-
-    // Rows
-    let signal_pins = [
-        gpiob.pb10.into_pull_down_input().erase(),
-        gpioa.pa5.into_pull_down_input().erase(),
-        gpioa.pa6.into_pull_down_input().erase(),
-        gpioa.pa7.into_pull_down_input().erase(),
-        gpiob.pb0.into_pull_down_input().erase(),
-        gpiob.pb1.into_pull_down_input().erase(),
-    ];
-
-    let mut matrix = PrimitiveBitset::new(0u32);
-
-    let mut delay = dp.TIM1.delay_us(&clocks);
-    loop {
-        delay.delay_us(50);
-        // Read keyboard matrix
-        for (r, pw) in power_pins.iter_mut().enumerate() {
-            pw.set_high();
-            delay.delay_us(10);
-            for (c, sg) in signal_pins.iter().enumerate() {
-                matrix.set(r * signal_pins.len() + c, sg.is_high());
-            }
-            pw.set_low();
+
+use keyboard_core::board::{Board, Led, Matrix, Parts};
+use shared_src::{
+    HostLeds, MainHalfCommand, MainHalfMessage, MainHalfStatus, MatrixBitset, MATRIX_PACKET_LEN,
+};
+
+mod board;
+mod bootloader;
+
+/// The board the firmware is built for. A port changes it along with the
+/// device and the interrupts of the app
+type Target = board::Stm32f401;
+
+type Packet = [u8; MATRIX_PACKET_LEN];
+
+/// Scans waiting for the link
+const QUEUE_LEN: usize = 2;
+
+systick_monotonic!(Mono, 1000);
+
+#[rtic::app(device = stm32f4xx_hal::pac, peripherals = false, dispatchers = [EXTI0])]
+mod app {
+    use super::*;
+
+    #[shared]
+    struct Shared {
+        status: MainHalfStatus,
+    }
+
+    #[local]
+    struct Local {
+        matrix: Matrix<<Target as Board>::Column, <Target as Board>::Row>,
+        delay: <Target as Board>::Delay,
+        link_tx: <Target as Board>::LinkTx,
+        link_rx: <Target as Board>::LinkRx,
+        /// Shows Num Lock
+        num_lock_led: Option<Led<<Target as Board>::LedPin>>,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> (Shared, Local) {
+        let Parts {
+            matrix,
+            link_tx,
+            link_rx,
+            delay,
+            led,
+        } = Target::init();
+        Mono::start(cx.core.SYST, Target::SYSCLK_HZ);
+
+        let (packet_sender, packets) = make_channel!(Packet, QUEUE_LEN);
+        scan::spawn(packet_sender).unwrap_or_else(|_| panic!());
+        link_transmit::spawn(packets).unwrap_or_else(|_| panic!());
+
+        let shared = Shared {
+            status: MainHalfStatus::default(),
+        };
+        let local = Local {
+            matrix,
+            delay,
+            link_tx,
+            link_rx,
+            num_lock_led: led,
+        };
+        (shared, local)
+    }
+
+    #[task(priority = 1, shared = [status], local = [matrix, delay])]
+    async fn scan(mut cx: scan::Context, mut packets: Sender<'static, Packet, QUEUE_LEN>) {
+        loop {
+            // Read keyboard matrix
+            let keys = MatrixBitset::from_words([cx.local.matrix.scan(cx.local.delay)]);
+            let mut packed_keydata = [0u8; MATRIX_PACKET_LEN];
+            keys.pack_7bit(&mut packed_keydata);
+            // A scan the link has no room for is dropped, the next one
+            // replaces it anyway
+            let _ = packets.try_send(packed_keydata);
+
+            // Low-power mode while the host sleeps: scan rarely, still fast
+            // enough for a keypress to wake it up
+            let suspended = cx.shared.status.lock(|status| status.suspended);
+            Mono::delay(if suspended { 10.millis() } else { 1.millis() }).await;
         }
-        
+    }
 
+    #[task(priority = 1, local = [link_tx])]
+    async fn link_transmit(
+        cx: link_transmit::Context,
+        mut packets: Receiver<'static, Packet, QUEUE_LEN>,
+    ) {
         // Send data to the main half (left stm32f1)
-        let packed_keydata = pack_keydata(matrix.get_raw());
-        for i in 0..5 {
-            let _ = block!(tx.write(packed_keydata[i]));
+        while let Ok(packet) = packets.recv().await {
+            for byte in packet {
+                let _ = nb::block!(cx.local.link_tx.write(byte));
+            }
         }
     }
-}
-
 
-
-/// Byte format:
-///  - [0] bit:
-///     - 1: It's the message first byte
-///     - 0: It's the message continuation byte \[1..5\]
-///  -   [1..7] bits: data
-fn pack_keydata(key_data: u32) -> [u8; 5] {
-    let le = key_data.to_le();
-    let mut result = [0; 5];
-    result[0] = le as u8 & 0x7f | 0x80;
-    result[1] = (le >> 7) as u8 & 0x7f;
-    result[2] = (le >> 14) as u8 & 0x7f;
-    result[3] = (le >> 21) as u8 & 0x7f;
-    result[4] = (le >> 28) as u8 & 0x7f;
-    result
-}
\ No newline at end of file
+    #[task(binds = USART2, priority = 2, shared = [status], local = [link_rx, num_lock_led])]
+    fn link_receive(mut cx: link_receive::Context) {
+        while let Ok(received) = cx.local.link_rx.read() {
+            match MainHalfMessage::unpack(received) {
+                Some(MainHalfMessage::Status(new_status)) => {
+                    cx.shared.status.lock(|status| *status = new_status);
+                    if let Some(led) = cx.local.num_lock_led.as_mut() {
+                        led.set(new_status.leds.get(HostLeds::NUM_LOCK) && !new_status.suspended);
+                    }
+                }
+                Some(MainHalfMessage::Command(MainHalfCommand::EnterBootloader)) => {
+                    Target::reboot_to_bootloader()
+                }
+                None => {}
+            }
+        }
+    }
+}
@@ -20,7 +20,7 @@ fn main() {
     for _ in 0..10_000_000 {
         for i in 0..29 {
             unsafe {
-                bitset.set(i, *PATTERN.get_unchecked(i as usize));
+                bitset.set(i, *PATTERN.get_unchecked(i));
                 std::hint::black_box(&bitset);
             }
         }
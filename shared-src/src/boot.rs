@@ -0,0 +1,86 @@
+//! Flash layout of the left half and the handshake between its DFU bootloader
+//! and the application.
+//!
+//! ```text
+//! 0x0800_0000  bootloader                      15K
+//! 0x0800_3C00  application descriptor page      1K
+//! 0x0800_4000  application                    109K
+//! 0x0801_F400  keymap page                      1K
+//! 0x0801_F800  settings pages (EEPROM)          2K
+//! ```
+
+pub const FLASH_START: u32 = 0x0800_0000;
+pub const PAGE_LEN: u32 = 1024;
+
+pub const APP_DESCRIPTOR_ADDRESS: u32 = 0x0800_3C00;
+pub const APP_START: u32 = 0x0800_4000;
+pub const APP_END: u32 = 0x0801_F400;
+pub const KEYMAP_ADDRESS: u32 = 0x0801_F400;
+/// First of the two pages of the settings EEPROM emulation
+pub const SETTINGS_ADDRESS: u32 = 0x0801_F800;
+
+/// In the last 8 bytes of RAM, which both linker scripts keep out of the RAM
+/// region so the word survives the reset untouched. The two images can't
+/// share a `.uninit` section, its address depends on the size of each one's
+/// statics
+pub const BOOTLOADER_REQUEST_ADDRESS: u32 = 0x2000_4FF8;
+/// Written to [`BOOTLOADER_REQUEST_ADDRESS`] by the application to stay in
+/// the bootloader after the reset
+pub const BOOTLOADER_MAGIC: u32 = 0xB007_10AD;
+
+/// What the bootloader knows about the flashed application, stored at
+/// [`APP_DESCRIPTOR_ADDRESS`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AppDescriptor {
+    /// Erased page: the application was flashed with a debug probe
+    Unverified,
+    /// Zeroed page: a download started and never completed
+    Incomplete,
+    /// Written after a complete download
+    Complete { len: u32, crc: u32 },
+}
+
+impl AppDescriptor {
+    pub const LEN: usize = 12;
+    const MAGIC: u32 = 0xA4B0_0C51;
+
+    pub fn pack(&self) -> [u8; Self::LEN] {
+        let words = match *self {
+            AppDescriptor::Unverified => [u32::MAX; 3],
+            AppDescriptor::Incomplete => [0; 3],
+            AppDescriptor::Complete { len, crc } => [Self::MAGIC, len, crc],
+        };
+        let mut data = [0; Self::LEN];
+        for (chunk, word) in data.chunks_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        data
+    }
+
+    /// Anything but an erased page or a valid descriptor counts as incomplete
+    pub fn unpack(data: &[u8; Self::LEN]) -> Self {
+        let word = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        match (word(0), word(4), word(8)) {
+            (u32::MAX, u32::MAX, u32::MAX) => AppDescriptor::Unverified,
+            (Self::MAGIC, len, crc) => AppDescriptor::Complete { len, crc },
+            _ => AppDescriptor::Incomplete,
+        }
+    }
+}
+
+/// CRC-32 (IEEE 802.3, as used by zip), nibble table to keep the bootloader
+/// small
+pub fn crc32(data: &[u8]) -> u32 {
+    #[rustfmt::skip]
+    const TABLE: [u32; 16] = [
+        0x0000_0000, 0x1DB7_1064, 0x3B6E_20C8, 0x26D9_30AC, 0x76DC_4190, 0x6B6B_51F4,
+        0x4DB2_6158, 0x5005_713C, 0xEDB8_8320, 0xF00F_9344, 0xD6D6_A3E8, 0xCB61_B38C,
+        0x9B64_C2B0, 0x86D3_D2D4, 0xA00A_E278, 0xBDBD_F21C,
+    ];
+    let mut crc = u32::MAX;
+    for &byte in data {
+        crc = TABLE[((crc ^ byte as u32) & 0x0F) as usize] ^ (crc >> 4);
+        crc = TABLE[((crc ^ (byte as u32 >> 4)) & 0x0F) as usize] ^ (crc >> 4);
+    }
+    !crc
+}
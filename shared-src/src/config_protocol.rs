@@ -0,0 +1,376 @@
+//! Request/response protocol of the raw HID configuration channel, shared by
+//! the firmware and host tools.
+//!
+//! Every message is a single 64-byte report:
+//!  - request:  [0] command, [1..] arguments
+//!  - response: [0] command echo, [1] [`Status`], [2..] payload
+//!
+//! Multi-byte values are little endian. Command ids start at 0x40 so the
+//! channel can be shared with VIA, whose commands use the low ids.
+
+pub const REPORT_LEN: usize = 64;
+
+/// Bumped on every incompatible change of the messages below
+pub const PROTOCOL_VERSION: u8 = 2;
+
+pub type Report = [u8; REPORT_LEN];
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Command {
+    GetVersion = 0x40,
+    GetKeymapEntry = 0x41,
+    SetKeymapEntry = 0x42,
+    GetMatrix = 0x43,
+    GetCounters = 0x44,
+    RebootToBootloader = 0x45,
+}
+
+impl Command {
+    pub const FIRST: u8 = 0x40;
+    pub const LAST: u8 = 0x45;
+}
+
+impl TryFrom<u8> for Command {
+    type Error = Status;
+
+    fn try_from(value: u8) -> Result<Self, Status> {
+        Ok(match value {
+            0x40 => Command::GetVersion,
+            0x41 => Command::GetKeymapEntry,
+            0x42 => Command::SetKeymapEntry,
+            0x43 => Command::GetMatrix,
+            0x44 => Command::GetCounters,
+            0x45 => Command::RebootToBootloader,
+            _ => return Err(Status::UnknownCommand),
+        })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Status {
+    Ok = 0x00,
+    UnknownCommand = 0x01,
+    InvalidArgument = 0x02,
+    Unsupported = 0x03,
+}
+
+impl TryFrom<u8> for Status {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, ()> {
+        Ok(match value {
+            0x00 => Status::Ok,
+            0x01 => Status::UnknownCommand,
+            0x02 => Status::InvalidArgument,
+            0x03 => Status::Unsupported,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// Kind of action a keymap entry performs, the meaning of [`RawKey::code`]
+/// depends on it
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum KeyKind {
+    /// Keyboard page usage
+    Keyboard = 0x00,
+    /// Consumer page usage
+    Consumer = 0x01,
+    /// System control: 1 power down, 2 sleep, 3 wake up
+    System = 0x02,
+    /// Mouse direction: 0 up, 1 down, 2 left, 3 right
+    MouseMove = 0x03,
+    /// Mouse button: 0 left, 1 right, 2 middle, 3 back, 4 forward
+    MouseButton = 0x04,
+    /// Same directions as [`KeyKind::MouseMove`]
+    MouseWheel = 0x05,
+    /// Firmware command: 0 toggle NKRO/6KRO, 1 reboot the left half into its
+    /// bootloader, 2 the same for the right half
+    Command = 0x06,
+}
+
+impl TryFrom<u8> for KeyKind {
+    type Error = Status;
+
+    fn try_from(value: u8) -> Result<Self, Status> {
+        Ok(match value {
+            0x00 => KeyKind::Keyboard,
+            0x01 => KeyKind::Consumer,
+            0x02 => KeyKind::System,
+            0x03 => KeyKind::MouseMove,
+            0x04 => KeyKind::MouseButton,
+            0x05 => KeyKind::MouseWheel,
+            0x06 => KeyKind::Command,
+            _ => return Err(Status::InvalidArgument),
+        })
+    }
+}
+
+/// Firmware independent encoding of a keymap entry
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct RawKey {
+    pub kind: KeyKind,
+    pub code: u16,
+}
+
+impl RawKey {
+    pub const fn new(kind: KeyKind, code: u16) -> Self {
+        Self { kind, code }
+    }
+
+    pub(crate) fn write(&self, out: &mut [u8]) {
+        out[0] = self.kind as u8;
+        out[1..3].copy_from_slice(&self.code.to_le_bytes());
+    }
+
+    pub(crate) fn read(data: &[u8]) -> Result<Self, Status> {
+        Ok(Self {
+            kind: KeyKind::try_from(data[0])?,
+            code: u16::from_le_bytes([data[1], data[2]]),
+        })
+    }
+}
+
+/// Position of a keymap entry
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct KeyPosition {
+    /// 0: left half, 1: right half
+    pub side: u8,
+    pub layer: u8,
+    /// Index in the matrix of the half
+    pub index: u8,
+}
+
+impl KeyPosition {
+    fn write(&self, out: &mut [u8]) {
+        out[0] = self.side;
+        out[1] = self.layer;
+        out[2] = self.index;
+    }
+
+    fn read(data: &[u8]) -> Self {
+        Self {
+            side: data[0],
+            layer: data[1],
+            index: data[2],
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl FirmwareVersion {
+    /// Parses a `major.minor.patch` string such as `CARGO_PKG_VERSION` at
+    /// compile time, components above 255 saturate
+    pub const fn parse(version: &str) -> Self {
+        let bytes = version.as_bytes();
+        let mut parts = [0u8; 3];
+        let mut part = 0;
+        let mut i = 0;
+        while i < bytes.len() && part < 3 {
+            match bytes[i] {
+                b'.' => part += 1,
+                b @ b'0'..=b'9' => {
+                    parts[part] = parts[part].saturating_mul(10).saturating_add(b - b'0')
+                }
+                _ => break,
+            }
+            i += 1;
+        }
+        Self {
+            major: parts[0],
+            minor: parts[1],
+            patch: parts[2],
+        }
+    }
+}
+
+/// Counters kept by the firmware since power-up, wrapping on overflow
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct DiagnosticCounters {
+    /// Complete matrix messages received from the right half
+    pub link_packets: u32,
+    /// Split link messages dropped because of a broken framing
+    pub link_errors: u32,
+    /// Keyboard reports accepted by the USB endpoint
+    pub reports_sent: u32,
+    /// Report writes retried because the endpoint was busy
+    pub report_retries: u32,
+    /// Reports replaced by the HID ErrorRollOver state
+    pub rollovers: u32,
+}
+
+impl DiagnosticCounters {
+    const LEN: usize = 5;
+
+    fn fields(&self) -> [u32; Self::LEN] {
+        [
+            self.link_packets,
+            self.link_errors,
+            self.reports_sent,
+            self.report_retries,
+            self.rollovers,
+        ]
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Request {
+    GetVersion,
+    GetKeymapEntry(KeyPosition),
+    SetKeymapEntry(KeyPosition, RawKey),
+    GetMatrix,
+    GetCounters,
+    /// Side of the half to reboot, 0: left, 1: right
+    RebootToBootloader(u8),
+}
+
+impl Request {
+    pub fn command(&self) -> Command {
+        match self {
+            Request::GetVersion => Command::GetVersion,
+            Request::GetKeymapEntry(_) => Command::GetKeymapEntry,
+            Request::SetKeymapEntry(_, _) => Command::SetKeymapEntry,
+            Request::GetMatrix => Command::GetMatrix,
+            Request::GetCounters => Command::GetCounters,
+            Request::RebootToBootloader(_) => Command::RebootToBootloader,
+        }
+    }
+
+    /// A report cut short is rejected
+    pub fn parse(data: &[u8]) -> Result<Self, Status> {
+        let report = whole_report(data)?;
+        let args = &report[1..];
+        Ok(match Command::try_from(report[0])? {
+            Command::GetVersion => Request::GetVersion,
+            Command::GetKeymapEntry => Request::GetKeymapEntry(KeyPosition::read(args)),
+            Command::SetKeymapEntry => {
+                Request::SetKeymapEntry(KeyPosition::read(args), RawKey::read(&args[3..])?)
+            }
+            Command::GetMatrix => Request::GetMatrix,
+            Command::GetCounters => Request::GetCounters,
+            Command::RebootToBootloader => Request::RebootToBootloader(args[0]),
+        })
+    }
+
+    pub fn write(&self, report: &mut Report) {
+        report.fill(0);
+        report[0] = self.command() as u8;
+        let args = &mut report[1..];
+        match self {
+            Request::GetKeymapEntry(position) => position.write(args),
+            Request::SetKeymapEntry(position, key) => {
+                position.write(args);
+                key.write(&mut args[3..]);
+            }
+            Request::RebootToBootloader(side) => args[0] = *side,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Response {
+    Version {
+        protocol: u8,
+        firmware: FirmwareVersion,
+    },
+    KeymapEntry(RawKey),
+    Matrix {
+        left: u32,
+        right: u32,
+    },
+    Counters(DiagnosticCounters),
+    /// Command succeeded without a payload
+    Done,
+    Error(Status),
+}
+
+impl Response {
+    pub fn write(&self, command: u8, report: &mut Report) {
+        report.fill(0);
+        report[0] = command;
+        report[1] = match self {
+            Response::Error(status) => *status as u8,
+            _ => Status::Ok as u8,
+        };
+        let payload = &mut report[2..];
+        match self {
+            Response::Version { protocol, firmware } => {
+                payload[..4].copy_from_slice(&[
+                    *protocol,
+                    firmware.major,
+                    firmware.minor,
+                    firmware.patch,
+                ]);
+            }
+            Response::KeymapEntry(key) => key.write(payload),
+            Response::Matrix { left, right } => {
+                payload[0..4].copy_from_slice(&left.to_le_bytes());
+                payload[4..8].copy_from_slice(&right.to_le_bytes());
+            }
+            Response::Counters(counters) => {
+                for (chunk, value) in payload.chunks_mut(4).zip(counters.fields()) {
+                    chunk.copy_from_slice(&value.to_le_bytes());
+                }
+            }
+            Response::Done | Response::Error(_) => {}
+        }
+    }
+
+    /// Decodes the response to `command`, used by host tools. A report cut
+    /// short is rejected
+    pub fn parse(command: Command, data: &[u8]) -> Result<Self, Status> {
+        let report = whole_report(data)?;
+        if report[0] != command as u8 {
+            return Err(Status::InvalidArgument);
+        }
+        let status = Status::try_from(report[1]).map_err(|_| Status::InvalidArgument)?;
+        if status != Status::Ok {
+            return Ok(Response::Error(status));
+        }
+
+        let payload = &report[2..];
+        let u32_at = |i: usize| {
+            u32::from_le_bytes([payload[i], payload[i + 1], payload[i + 2], payload[i + 3]])
+        };
+        Ok(match command {
+            Command::GetVersion => Response::Version {
+                protocol: payload[0],
+                firmware: FirmwareVersion {
+                    major: payload[1],
+                    minor: payload[2],
+                    patch: payload[3],
+                },
+            },
+            Command::GetKeymapEntry => Response::KeymapEntry(RawKey::read(payload)?),
+            Command::GetMatrix => Response::Matrix {
+                left: u32_at(0),
+                right: u32_at(4),
+            },
+            Command::GetCounters => Response::Counters(DiagnosticCounters {
+                link_packets: u32_at(0),
+                link_errors: u32_at(4),
+                reports_sent: u32_at(8),
+                report_retries: u32_at(12),
+                rollovers: u32_at(16),
+            }),
+            Command::SetKeymapEntry | Command::RebootToBootloader => Response::Done,
+        })
+    }
+}
+
+/// The report `data` starts with, extra bytes are ignored
+fn whole_report(data: &[u8]) -> Result<&Report, Status> {
+    data.get(..REPORT_LEN)
+        .and_then(|report| report.try_into().ok())
+        .ok_or(Status::InvalidArgument)
+}
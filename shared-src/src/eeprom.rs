@@ -0,0 +1,398 @@
+//! EEPROM emulation: small values under one byte keys, kept on two flash
+//! pages that take turns.
+//!
+//! Page layout, words little endian:
+//! ```text
+//! +0  receiving marker (u16) | generation (u16)    written when the page is started
+//! +4  active marker (u32)                          written once the copy is complete
+//! +8  records, each padded to a word:
+//!     key (u8), value length (u8), CRC (u16), value
+//! ```
+//!
+//! Writes append a record to the active page, a read takes the last record
+//! of the key. When the page is full the latest record of every key is
+//! copied to the other page, which then becomes active with the next
+//! generation, and the old page is erased.
+//!
+//! Power loss at any point leaves either the old or the new value: a page
+//! without the active marker is ignored, of two active pages the newer
+//! generation wins, and a torn record fails its CRC. A torn record makes the
+//! next write compact the page instead of appending after it.
+//!
+//! Flash is accessed through [`NorFlash`] so the algorithm runs unchanged on
+//! the host against a simulated flash.
+
+use crate::boot::crc32;
+use embedded_storage::nor_flash::NorFlash;
+
+/// Longest value a key can hold
+pub const MAX_VALUE_LEN: usize = 32;
+/// 0xFF is left out as an erased record header starts with it
+pub const MAX_KEY: u8 = 0xFE;
+
+const WORD_LEN: u32 = 4;
+const PAGE_HEADER_LEN: u32 = 2 * WORD_LEN;
+const RECORD_HEADER_LEN: usize = 4;
+const MAX_RECORD_LEN: usize = RECORD_HEADER_LEN + MAX_VALUE_LEN;
+
+const RECEIVING_MARKER: u16 = 0xEE9A;
+const ACTIVE_MARKER: u32 = 0x0A5C_71FE;
+const ERASED: u32 = u32::MAX;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error<E> {
+    Flash(E),
+    InvalidKey,
+    ValueTooLong,
+    /// The latest values of all keys don't fit on a page
+    Full,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(error: E) -> Self {
+        Error::Flash(error)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum PageState {
+    Active(u16),
+    /// Erased, half-written or damaged
+    Unused,
+}
+
+enum Slot {
+    /// Erased header, nothing follows
+    End,
+    Valid {
+        key: u8,
+        len: usize,
+        next: u32,
+    },
+    /// Readable header but the value doesn't match the CRC
+    Torn {
+        next: u32,
+    },
+    /// Header that can't be a record, the rest of the page is unusable
+    Broken,
+}
+
+/// State of the emulation, the flash itself is passed to every call
+pub struct Eeprom {
+    pages: [u32; 2],
+    page_len: u32,
+    active: usize,
+    generation: u16,
+    /// Offset of the first free word of the active page
+    free: u32,
+    /// A torn or broken record was found, the next write compacts
+    dirty: bool,
+}
+
+impl Eeprom {
+    /// Finds the active page of the two starting at `first_page`, or
+    /// formats the first one when there is none
+    pub fn mount<F: NorFlash>(
+        flash: &mut F,
+        first_page: u32,
+        page_len: u32,
+    ) -> Result<Self, Error<F::Error>> {
+        let word_len = WORD_LEN as usize;
+        assert!(word_len.is_multiple_of(F::READ_SIZE) && word_len.is_multiple_of(F::WRITE_SIZE));
+        let erase_len = F::ERASE_SIZE as u32;
+        assert!(first_page.is_multiple_of(erase_len) && page_len.is_multiple_of(erase_len));
+        assert!(page_len as usize >= PAGE_HEADER_LEN as usize + MAX_RECORD_LEN);
+
+        let mut eeprom = Self {
+            pages: [first_page, first_page + page_len],
+            page_len,
+            active: 0,
+            generation: 0,
+            free: first_page + PAGE_HEADER_LEN,
+            dirty: false,
+        };
+        let states = [
+            page_state(flash, eeprom.pages[0])?,
+            page_state(flash, eeprom.pages[1])?,
+        ];
+        let (active, generation) = match states {
+            [PageState::Active(first), PageState::Active(second)] => {
+                if (second.wrapping_sub(first) as i16) > 0 {
+                    (1, second)
+                } else {
+                    (0, first)
+                }
+            }
+            [PageState::Active(generation), _] => (0, generation),
+            [_, PageState::Active(generation)] => (1, generation),
+            [PageState::Unused, PageState::Unused] => {
+                eeprom.start_page(flash, 0, 0)?;
+                flash.write(eeprom.pages[0] + WORD_LEN, &ACTIVE_MARKER.to_le_bytes())?;
+                return Ok(eeprom);
+            }
+        };
+
+        eeprom.active = active;
+        eeprom.generation = generation;
+        eeprom.scan(flash)?;
+        Ok(eeprom)
+    }
+
+    /// Copies the value of `key` to `value` and returns its length, `None`
+    /// if the key was never written
+    pub fn read<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        key: u8,
+        value: &mut [u8],
+    ) -> Result<Option<usize>, Error<F::Error>> {
+        let mut record = [0; MAX_RECORD_LEN];
+        let Some(offset) = self.find_latest(flash, key, self.page_start())? else {
+            return Ok(None);
+        };
+        let Slot::Valid { len, .. } = self.read_slot(flash, offset, &mut record)? else {
+            return Ok(None);
+        };
+        value
+            .get_mut(..len)
+            .ok_or(Error::ValueTooLong)?
+            .copy_from_slice(&record[RECORD_HEADER_LEN..RECORD_HEADER_LEN + len]);
+        Ok(Some(len))
+    }
+
+    /// Stores `value` under `key`, rewriting an unchanged value is a no-op
+    pub fn write<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        key: u8,
+        value: &[u8],
+    ) -> Result<(), Error<F::Error>> {
+        if key > MAX_KEY {
+            return Err(Error::InvalidKey);
+        }
+        if value.len() > MAX_VALUE_LEN {
+            return Err(Error::ValueTooLong);
+        }
+
+        let mut current = [0; MAX_VALUE_LEN];
+        if let Some(len) = self.read(flash, key, &mut current)? {
+            if current[..len] == *value {
+                return Ok(());
+            }
+        }
+
+        if self.dirty || self.free + record_len(value.len()) > self.page_end() {
+            return self.compact(flash, key, value);
+        }
+        match write_record(flash, self.free, key, value) {
+            Ok(len) => {
+                self.free += len;
+                Ok(())
+            }
+            Err(error) => {
+                // Whatever made it to the flash is garbage now
+                self.dirty = true;
+                Err(error)
+            }
+        }
+    }
+
+    fn page_start(&self) -> u32 {
+        self.pages[self.active] + PAGE_HEADER_LEN
+    }
+
+    fn page_end(&self) -> u32 {
+        self.pages[self.active] + self.page_len
+    }
+
+    fn scan<F: NorFlash>(&mut self, flash: &mut F) -> Result<(), Error<F::Error>> {
+        let mut record = [0; MAX_RECORD_LEN];
+        let mut offset = self.page_start();
+        self.free = loop {
+            match self.read_slot(flash, offset, &mut record)? {
+                Slot::End => break offset,
+                Slot::Valid { next, .. } => offset = next,
+                Slot::Torn { next } => {
+                    self.dirty = true;
+                    offset = next;
+                }
+                Slot::Broken => {
+                    self.dirty = true;
+                    break self.page_end();
+                }
+            }
+        };
+        Ok(())
+    }
+
+    /// Offset of the last valid record of `key` at or after `offset`
+    fn find_latest<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        key: u8,
+        mut offset: u32,
+    ) -> Result<Option<u32>, Error<F::Error>> {
+        let mut record = [0; MAX_RECORD_LEN];
+        let mut latest = None;
+        while offset < self.free {
+            match self.read_slot(flash, offset, &mut record)? {
+                Slot::Valid {
+                    key: record_key,
+                    next,
+                    ..
+                } => {
+                    if record_key == key {
+                        latest = Some(offset);
+                    }
+                    offset = next;
+                }
+                Slot::Torn { next } => offset = next,
+                Slot::End | Slot::Broken => break,
+            }
+        }
+        Ok(latest)
+    }
+
+    /// Reads the record at `offset` of the active page into `record`
+    fn read_slot<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        offset: u32,
+        record: &mut [u8; MAX_RECORD_LEN],
+    ) -> Result<Slot, Error<F::Error>> {
+        if offset + WORD_LEN > self.page_end() {
+            return Ok(Slot::End);
+        }
+        flash.read(offset, &mut record[..RECORD_HEADER_LEN])?;
+        if u32::from_le_bytes([record[0], record[1], record[2], record[3]]) == ERASED {
+            return Ok(Slot::End);
+        }
+
+        let (key, len) = (record[0], record[1] as usize);
+        let next = offset + record_len(len);
+        if key > MAX_KEY || len > MAX_VALUE_LEN || next > self.page_end() {
+            return Ok(Slot::Broken);
+        }
+        let padded = record_len(len) as usize;
+        flash.read(
+            offset + RECORD_HEADER_LEN as u32,
+            &mut record[RECORD_HEADER_LEN..padded],
+        )?;
+
+        let crc = u16::from_le_bytes([record[2], record[3]]);
+        Ok(
+            if crc == record_crc(key, &record[RECORD_HEADER_LEN..RECORD_HEADER_LEN + len]) {
+                Slot::Valid { key, len, next }
+            } else {
+                Slot::Torn { next }
+            },
+        )
+    }
+
+    /// Moves the latest records and the new one to the other page
+    fn compact<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        key: u8,
+        value: &[u8],
+    ) -> Result<(), Error<F::Error>> {
+        let target = 1 - self.active;
+        let generation = self.generation.wrapping_add(1);
+        self.start_page(flash, target, generation)?;
+
+        let target_end = self.pages[target] + self.page_len;
+        let mut free = self.pages[target] + PAGE_HEADER_LEN;
+        let mut record = [0; MAX_RECORD_LEN];
+        let mut offset = self.page_start();
+        while offset < self.free {
+            let (record_key, len, next) = match self.read_slot(flash, offset, &mut record)? {
+                Slot::Valid { key, len, next } => (key, len, next),
+                Slot::Torn { next } => {
+                    offset = next;
+                    continue;
+                }
+                Slot::End | Slot::Broken => break,
+            };
+            if record_key != key && self.find_latest(flash, record_key, next)?.is_none() {
+                let value = &record[RECORD_HEADER_LEN..RECORD_HEADER_LEN + len];
+                if free + record_len(len) > target_end {
+                    return Err(Error::Full);
+                }
+                free += write_record(flash, free, record_key, value)?;
+            }
+            offset = next;
+        }
+        if free + record_len(value.len()) > target_end {
+            return Err(Error::Full);
+        }
+        free += write_record(flash, free, key, value)?;
+
+        // The switch: from here on the target page wins at mount
+        flash.write(self.pages[target] + WORD_LEN, &ACTIVE_MARKER.to_le_bytes())?;
+        let old = self.pages[self.active];
+        self.active = target;
+        self.generation = generation;
+        self.free = free;
+        self.dirty = false;
+        flash.erase(old, old + self.page_len)?;
+        Ok(())
+    }
+
+    /// Erases page `index` and marks it as receiving `generation`
+    fn start_page<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        index: usize,
+        generation: u16,
+    ) -> Result<(), Error<F::Error>> {
+        let page = self.pages[index];
+        flash.erase(page, page + self.page_len)?;
+        let marker = RECEIVING_MARKER as u32 | (generation as u32) << 16;
+        flash.write(page, &marker.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+fn page_state<F: NorFlash>(flash: &mut F, page: u32) -> Result<PageState, Error<F::Error>> {
+    let mut header = [0; PAGE_HEADER_LEN as usize];
+    flash.read(page, &mut header)?;
+    let receiving = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let active = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    Ok(
+        if receiving as u16 == RECEIVING_MARKER && active == ACTIVE_MARKER {
+            PageState::Active((receiving >> 16) as u16)
+        } else {
+            PageState::Unused
+        },
+    )
+}
+
+fn record_len(value_len: usize) -> u32 {
+    (RECORD_HEADER_LEN + value_len).next_multiple_of(WORD_LEN as usize) as u32
+}
+
+/// Low half of the CRC-32 of key, length and value
+fn record_crc(key: u8, value: &[u8]) -> u16 {
+    let mut data = [0; 2 + MAX_VALUE_LEN];
+    data[0] = key;
+    data[1] = value.len() as u8;
+    data[2..2 + value.len()].copy_from_slice(value);
+    crc32(&data[..2 + value.len()]) as u16
+}
+
+/// Writes a record in one go, header first, and returns its padded length
+fn write_record<F: NorFlash>(
+    flash: &mut F,
+    offset: u32,
+    key: u8,
+    value: &[u8],
+) -> Result<u32, Error<F::Error>> {
+    let len = record_len(value.len());
+    let mut record = [0xFF; MAX_RECORD_LEN];
+    record[0] = key;
+    record[1] = value.len() as u8;
+    record[2..4].copy_from_slice(&record_crc(key, value).to_le_bytes());
+    record[RECORD_HEADER_LEN..RECORD_HEADER_LEN + value.len()].copy_from_slice(value);
+    flash.write(offset, &record[..len as usize])?;
+    Ok(len)
+}
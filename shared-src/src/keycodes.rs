@@ -0,0 +1,237 @@
+//! Names of keymap entries, as the keymap file and the host tools spell them,
+//! and the [`RawKey`] each stands for.
+//!
+//! Keyboard and consumer keys carry the variant names of the
+//! usbd-human-interface-device pages, the other kinds those of the firmware
+//! enums, so the build script can emit them as they are. In the keymap file a
+//! keyboard key is written bare (`Q`, `LeftShift` or a short name such as
+//! `LSft`), any other kind inside its macro name (`consumer(PlayPause)`).
+
+use crate::config_protocol::{KeyKind, RawKey};
+use core::fmt;
+
+/// Spelling of a position without a key
+pub const NO_KEY: &str = "___";
+
+/// Keyboard page usages
+#[rustfmt::skip]
+pub const KEYBOARD: &[(&str, u16)] = &[
+    ("NoEventIndicated", 0x00), ("ErrorRollOver", 0x01), ("POSTFail", 0x02),
+    ("ErrorUndefine", 0x03), ("A", 0x04), ("B", 0x05), ("C", 0x06), ("D", 0x07), ("E", 0x08),
+    ("F", 0x09), ("G", 0x0A), ("H", 0x0B), ("I", 0x0C), ("J", 0x0D), ("K", 0x0E), ("L", 0x0F),
+    ("M", 0x10), ("N", 0x11), ("O", 0x12), ("P", 0x13), ("Q", 0x14), ("R", 0x15), ("S", 0x16),
+    ("T", 0x17), ("U", 0x18), ("V", 0x19), ("W", 0x1A), ("X", 0x1B), ("Y", 0x1C), ("Z", 0x1D),
+    ("Keyboard1", 0x1E), ("Keyboard2", 0x1F), ("Keyboard3", 0x20), ("Keyboard4", 0x21),
+    ("Keyboard5", 0x22), ("Keyboard6", 0x23), ("Keyboard7", 0x24), ("Keyboard8", 0x25),
+    ("Keyboard9", 0x26), ("Keyboard0", 0x27), ("ReturnEnter", 0x28), ("Escape", 0x29),
+    ("DeleteBackspace", 0x2A), ("Tab", 0x2B), ("Space", 0x2C), ("Minus", 0x2D), ("Equal", 0x2E),
+    ("LeftBrace", 0x2F), ("RightBrace", 0x30), ("Backslash", 0x31), ("NonUSHash", 0x32),
+    ("Semicolon", 0x33), ("Apostrophe", 0x34), ("Grave", 0x35), ("Comma", 0x36), ("Dot", 0x37),
+    ("ForwardSlash", 0x38), ("CapsLock", 0x39), ("F1", 0x3A), ("F2", 0x3B), ("F3", 0x3C),
+    ("F4", 0x3D), ("F5", 0x3E), ("F6", 0x3F), ("F7", 0x40), ("F8", 0x41), ("F9", 0x42),
+    ("F10", 0x43), ("F11", 0x44), ("F12", 0x45), ("PrintScreen", 0x46), ("ScrollLock", 0x47),
+    ("Pause", 0x48), ("Insert", 0x49), ("Home", 0x4A), ("PageUp", 0x4B),
+    ("DeleteForward", 0x4C), ("End", 0x4D), ("PageDown", 0x4E), ("RightArrow", 0x4F),
+    ("LeftArrow", 0x50), ("DownArrow", 0x51), ("UpArrow", 0x52),
+    ("KeypadNumLockAndClear", 0x53), ("KeypadDivide", 0x54), ("KeypadMultiply", 0x55),
+    ("KeypadSubtract", 0x56), ("KeypadAdd", 0x57), ("KeypadEnter", 0x58), ("Keypad1", 0x59),
+    ("Keypad2", 0x5A), ("Keypad3", 0x5B), ("Keypad4", 0x5C), ("Keypad5", 0x5D),
+    ("Keypad6", 0x5E), ("Keypad7", 0x5F), ("Keypad8", 0x60), ("Keypad9", 0x61),
+    ("Keypad0", 0x62), ("KeypadDot", 0x63), ("NonUSBackslash", 0x64), ("Application", 0x65),
+    ("Power", 0x66), ("KeypadEqual", 0x67), ("F13", 0x68), ("F14", 0x69), ("F15", 0x6A),
+    ("F16", 0x6B), ("F17", 0x6C), ("F18", 0x6D), ("F19", 0x6E), ("F20", 0x6F), ("F21", 0x70),
+    ("F22", 0x71), ("F23", 0x72), ("F24", 0x73), ("Execute", 0x74), ("Help", 0x75),
+    ("Menu", 0x76), ("Select", 0x77), ("Stop", 0x78), ("Again", 0x79), ("Undo", 0x7A),
+    ("Cut", 0x7B), ("Copy", 0x7C), ("Paste", 0x7D), ("Find", 0x7E), ("Mute", 0x7F),
+    ("VolumeUp", 0x80), ("VolumeDown", 0x81), ("LockingCapsLock", 0x82),
+    ("LockingNumLock", 0x83), ("LockingScrollLock", 0x84), ("KeypadComma", 0x85),
+    ("KeypadEqualSign", 0x86), ("Kanji1", 0x87), ("Kanji2", 0x88), ("Kanji3", 0x89),
+    ("Kanji4", 0x8A), ("Kanji5", 0x8B), ("Kanji6", 0x8C), ("Kanji7", 0x8D), ("Kanji8", 0x8E),
+    ("Kanji9", 0x8F), ("LANG1", 0x90), ("LANG2", 0x91), ("LANG3", 0x92), ("LANG4", 0x93),
+    ("LANG5", 0x94), ("LANG6", 0x95), ("LANG7", 0x96), ("LANG8", 0x97), ("LANG9", 0x98),
+    ("AlternateErase", 0x99), ("SysReqAttention", 0x9A), ("Cancel", 0x9B), ("Clear", 0x9C),
+    ("Prior", 0x9D), ("Return", 0x9E), ("Separator", 0x9F), ("Out", 0xA0), ("Oper", 0xA1),
+    ("ClearAgain", 0xA2), ("CrSelProps", 0xA3), ("ExSel", 0xA4), ("LeftControl", 0xE0),
+    ("LeftShift", 0xE1), ("LeftAlt", 0xE2), ("LeftGUI", 0xE3), ("RightControl", 0xE4),
+    ("RightShift", 0xE5), ("RightAlt", 0xE6), ("RightGUI", 0xE7),
+];
+
+/// Short names of keyboard usages, QMK's without the `KC_` prefix where there
+/// is one
+#[rustfmt::skip]
+pub const KEYBOARD_ALIASES: &[(&str, &str)] = &[
+    ("1", "Keyboard1"), ("2", "Keyboard2"), ("3", "Keyboard3"), ("4", "Keyboard4"),
+    ("5", "Keyboard5"), ("6", "Keyboard6"), ("7", "Keyboard7"), ("8", "Keyboard8"),
+    ("9", "Keyboard9"), ("0", "Keyboard0"),
+    ("Esc", "Escape"), ("Ent", "ReturnEnter"), ("Bspc", "DeleteBackspace"),
+    ("Del", "DeleteForward"), ("Spc", "Space"), ("Caps", "CapsLock"),
+    ("Mins", "Minus"), ("Eql", "Equal"), ("Lbrc", "LeftBrace"), ("Rbrc", "RightBrace"),
+    ("Bsls", "Backslash"), ("Scln", "Semicolon"), ("Quot", "Apostrophe"),
+    ("Grv", "Grave"), ("Comm", "Comma"), ("Slsh", "ForwardSlash"),
+    ("LCtl", "LeftControl"), ("LSft", "LeftShift"), ("LAlt", "LeftAlt"), ("LGui", "LeftGUI"),
+    ("RCtl", "RightControl"), ("RSft", "RightShift"), ("RAlt", "RightAlt"), ("RGui", "RightGUI"),
+    ("Left", "LeftArrow"), ("Down", "DownArrow"), ("Up", "UpArrow"), ("Rght", "RightArrow"),
+    ("Ins", "Insert"), ("PgUp", "PageUp"), ("PgDn", "PageDown"),
+    ("PScr", "PrintScreen"), ("ScrLk", "ScrollLock"), ("App", "Application"),
+    ("VolU", "VolumeUp"), ("VolD", "VolumeDown"),
+    ("NumLk", "KeypadNumLockAndClear"), ("PSls", "KeypadDivide"), ("PAst", "KeypadMultiply"),
+    ("PMns", "KeypadSubtract"), ("PPls", "KeypadAdd"), ("PEnt", "KeypadEnter"),
+    ("P1", "Keypad1"), ("P2", "Keypad2"), ("P3", "Keypad3"), ("P4", "Keypad4"),
+    ("P5", "Keypad5"), ("P6", "Keypad6"), ("P7", "Keypad7"), ("P8", "Keypad8"),
+    ("P9", "Keypad9"), ("P0", "Keypad0"), ("PDot", "KeypadDot"),
+];
+
+/// Consumer page usages a key is likely bound to
+#[rustfmt::skip]
+pub const CONSUMER: &[(&str, u16)] = &[
+    ("DisplayBrightnessIncrement", 0x006F), ("DisplayBrightnessDecrement", 0x0070),
+    ("FastForward", 0x00B3), ("Rewind", 0x00B4), ("ScanNextTrack", 0x00B5),
+    ("ScanPreviousTrack", 0x00B6), ("Stop", 0x00B7), ("Eject", 0x00B8),
+    ("PlayPause", 0x00CD), ("Mute", 0x00E2), ("VolumeIncrement", 0x00E9),
+    ("VolumeDecrement", 0x00EA), ("ALEmailReader", 0x018A), ("ALCalculator", 0x0192),
+    ("ALLocalMachineBrowser", 0x0194), ("ALInternetBrowser", 0x0196),
+    ("ALCommandLineProcessorRun", 0x01A0), ("ALFileBrowser", 0x01B4),
+    ("ACSearch", 0x0221), ("ACHome", 0x0223), ("ACBack", 0x0224), ("ACForward", 0x0225),
+    ("ACStop", 0x0226), ("ACRefresh", 0x0227), ("ACBookmarks", 0x022A),
+];
+
+/// `SystemControl` of the firmware
+pub const SYSTEM: &[(&str, u16)] = &[("PowerDown", 1), ("Sleep", 2), ("WakeUp", 3)];
+
+/// `MouseDirection` of the firmware, for both [`KeyKind::MouseMove`] and
+/// [`KeyKind::MouseWheel`]
+pub const MOUSE_DIRECTION: &[(&str, u16)] = &[("Up", 0), ("Down", 1), ("Left", 2), ("Right", 3)];
+
+/// `MouseButton` of the firmware
+pub const MOUSE_BUTTON: &[(&str, u16)] = &[
+    ("Left", 0),
+    ("Right", 1),
+    ("Middle", 2),
+    ("Back", 3),
+    ("Forward", 4),
+];
+
+/// `KeyCommand` of the firmware
+pub const COMMAND: &[(&str, u16)] = &[
+    ("ToggleKeyboardMode", 0),
+    ("EnterBootloader", 1),
+    ("EnterRightBootloader", 2),
+];
+
+const KINDS: [KeyKind; 7] = [
+    KeyKind::Keyboard,
+    KeyKind::Consumer,
+    KeyKind::System,
+    KeyKind::MouseMove,
+    KeyKind::MouseButton,
+    KeyKind::MouseWheel,
+    KeyKind::Command,
+];
+
+/// Name of the `layouts_def` macro building an entry of `kind`, also its
+/// prefix in the keymap file
+pub fn kind_name(kind: KeyKind) -> &'static str {
+    match kind {
+        KeyKind::Keyboard => "key",
+        KeyKind::Consumer => "consumer",
+        KeyKind::System => "system",
+        KeyKind::MouseMove => "mouse_move",
+        KeyKind::MouseButton => "mouse_button",
+        KeyKind::MouseWheel => "mouse_wheel",
+        KeyKind::Command => "command",
+    }
+}
+
+fn names(kind: KeyKind) -> &'static [(&'static str, u16)] {
+    match kind {
+        KeyKind::Keyboard => KEYBOARD,
+        KeyKind::Consumer => CONSUMER,
+        KeyKind::System => SYSTEM,
+        KeyKind::MouseMove | KeyKind::MouseWheel => MOUSE_DIRECTION,
+        KeyKind::MouseButton => MOUSE_BUTTON,
+        KeyKind::Command => COMMAND,
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ParseError {
+    UnknownKind,
+    UnknownKey,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ParseError::UnknownKind => "unknown key kind",
+            ParseError::UnknownKey => "unknown key",
+        })
+    }
+}
+
+/// Entry spelled `token`
+pub fn parse(token: &str) -> Result<RawKey, ParseError> {
+    let (kind, name) = match token.strip_suffix(')').and_then(|t| t.split_once('(')) {
+        Some((prefix, name)) => {
+            let kind = KINDS
+                .into_iter()
+                .find(|&kind| kind != KeyKind::Keyboard && kind_name(kind) == prefix)
+                .ok_or(ParseError::UnknownKind)?;
+            (kind, name)
+        }
+        None if token == NO_KEY => (KeyKind::Keyboard, "NoEventIndicated"),
+        None => {
+            let name = KEYBOARD_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == token)
+                .map_or(token, |(_, name)| name);
+            (KeyKind::Keyboard, name)
+        }
+    };
+    names(kind)
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|&(_, code)| RawKey::new(kind, code))
+        .ok_or(ParseError::UnknownKey)
+}
+
+/// Name of an entry known to this module, see [`name`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Name {
+    pub kind: KeyKind,
+    /// Variant of the enum `kind` stands for
+    pub variant: &'static str,
+}
+
+impl Name {
+    /// Short name of a keyboard key, if it has one
+    pub fn alias(&self) -> Option<&'static str> {
+        match self.kind {
+            KeyKind::Keyboard => KEYBOARD_ALIASES
+                .iter()
+                .find(|(_, name)| *name == self.variant)
+                .map(|&(alias, _)| alias),
+            _ => None,
+        }
+    }
+}
+
+/// Spelling of the keymap file, [`parse`] reads it back
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            KeyKind::Keyboard if self.variant == "NoEventIndicated" => f.write_str(NO_KEY),
+            KeyKind::Keyboard => f.write_str(self.alias().unwrap_or(self.variant)),
+            kind => write!(f, "{}({})", kind_name(kind), self.variant),
+        }
+    }
+}
+
+pub fn name(key: RawKey) -> Option<Name> {
+    names(key.kind)
+        .iter()
+        .find(|&&(_, code)| code == key.code)
+        .map(|&(variant, _)| Name {
+            kind: key.kind,
+            variant,
+        })
+}
@@ -0,0 +1,107 @@
+//! Serialised keymap, stored in flash by the left half.
+//!
+//! Layout, multi-byte values little endian:
+//!  - magic (u16), format version (u8)
+//!  - left layer count, right layer count, keys per layer (u8 each)
+//!  - entries as [`RawKey`] (kind u8, code u16): the left layers, then the
+//!    right ones, each in matrix order
+//!  - CRC-32 of everything before it (u32)
+//!
+//! Entries use the configuration protocol encoding instead of the firmware
+//! types, so a keymap saved by one firmware version loads in the next. The
+//! shape is stored too, a firmware with more layers or keys keeps its
+//! defaults where the blob has nothing.
+
+use crate::boot::crc32;
+use crate::config_protocol::RawKey;
+
+pub const MAGIC: u16 = 0x4B4D;
+/// Bumped when the layout above changes
+pub const FORMAT_VERSION: u8 = 1;
+
+const HEADER_LEN: usize = 6;
+const ENTRY_LEN: usize = 3;
+const CRC_LEN: usize = 4;
+
+/// Layer counts and layer size of a keymap
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct KeymapShape {
+    pub left_layers: u8,
+    pub right_layers: u8,
+    pub layer_len: u8,
+}
+
+impl KeymapShape {
+    pub const fn entry_count(&self) -> usize {
+        (self.left_layers as usize + self.right_layers as usize) * self.layer_len as usize
+    }
+
+    pub const fn blob_len(&self) -> usize {
+        HEADER_LEN + self.entry_count() * ENTRY_LEN + CRC_LEN
+    }
+
+    /// Side (0: left, 1: right), layer and index of the `entry`-th key
+    pub fn position(&self, entry: usize) -> (u8, u8, u8) {
+        let layer = entry / self.layer_len as usize;
+        let index = (entry % self.layer_len as usize) as u8;
+        if layer < self.left_layers as usize {
+            (0, layer as u8, index)
+        } else {
+            (1, (layer - self.left_layers as usize) as u8, index)
+        }
+    }
+}
+
+/// Writes `keys`, `shape.entry_count()` of them in the order described
+/// above, and returns the blob length. `None` if `out` is too short
+pub fn encode(
+    shape: KeymapShape,
+    keys: impl IntoIterator<Item = RawKey>,
+    out: &mut [u8],
+) -> Option<usize> {
+    let len = shape.blob_len();
+    let out = out.get_mut(..len)?;
+    out[0..2].copy_from_slice(&MAGIC.to_le_bytes());
+    out[2] = FORMAT_VERSION;
+    out[3] = shape.left_layers;
+    out[4] = shape.right_layers;
+    out[5] = shape.layer_len;
+
+    let entries = &mut out[HEADER_LEN..len - CRC_LEN];
+    let mut chunks = entries.chunks_mut(ENTRY_LEN);
+    for key in keys.into_iter().take(shape.entry_count()) {
+        key.write(chunks.next()?);
+    }
+    if chunks.next().is_some() {
+        return None;
+    }
+
+    let crc = crc32(&out[..len - CRC_LEN]);
+    out[len - CRC_LEN..].copy_from_slice(&crc.to_le_bytes());
+    Some(len)
+}
+
+/// Checks the header and the CRC, `None` for an erased or damaged blob.
+/// Entries of a key kind this version doesn't know come out as `None`
+pub fn decode(data: &[u8]) -> Option<(KeymapShape, impl Iterator<Item = Option<RawKey>> + '_)> {
+    let header = data.get(..HEADER_LEN)?;
+    if u16::from_le_bytes([header[0], header[1]]) != MAGIC || header[2] != FORMAT_VERSION {
+        return None;
+    }
+    let shape = KeymapShape {
+        left_layers: header[3],
+        right_layers: header[4],
+        layer_len: header[5],
+    };
+
+    let data = data.get(..shape.blob_len())?;
+    let (content, crc) = data.split_at(data.len() - CRC_LEN);
+    if crc32(content) != u32::from_le_bytes([crc[0], crc[1], crc[2], crc[3]]) {
+        return None;
+    }
+
+    let entries = content[HEADER_LEN..]
+        .chunks(ENTRY_LEN)
+        .map(|entry| RawKey::read(entry).ok());
+    Some((shape, entries))
+}
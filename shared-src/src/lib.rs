@@ -62,7 +62,15 @@
 // 
 #![no_std]
 
-use core::ops::{BitAnd, BitOr, Not, Shl, Shr};
+use core::ops::{BitAnd, BitOr, Not, Range, Shl, Shr};
+
+pub mod boot;
+pub mod config_protocol;
+pub mod eeprom;
+pub mod keycodes;
+pub mod keymap_blob;
+pub mod storage;
+pub mod via;
 
 pub trait BitsetWord:
     Copy
@@ -76,32 +84,57 @@ pub trait BitsetWord:
 {
     fn one() -> Self;
     fn zero() -> Self;
+    fn trailing_zeros(self) -> u32;
+    fn count_ones(self) -> u32;
 }
 
 impl BitsetWord for u8 {
     #[inline(always)] fn one() -> Self { 1 }
     #[inline(always)] fn zero() -> Self { 0 }
+    #[inline(always)] fn trailing_zeros(self) -> u32 { u8::trailing_zeros(self) }
+    #[inline(always)] fn count_ones(self) -> u32 { u8::count_ones(self) }
 }
 impl BitsetWord for u16 {
     #[inline(always)] fn one() -> Self { 1 }
     #[inline(always)] fn zero() -> Self { 0 }
+    #[inline(always)] fn trailing_zeros(self) -> u32 { u16::trailing_zeros(self) }
+    #[inline(always)] fn count_ones(self) -> u32 { u16::count_ones(self) }
 }
 impl BitsetWord for u32 {
     #[inline(always)] fn one() -> Self { 1 }
     #[inline(always)] fn zero() -> Self { 0 }
+    #[inline(always)] fn trailing_zeros(self) -> u32 { u32::trailing_zeros(self) }
+    #[inline(always)] fn count_ones(self) -> u32 { u32::count_ones(self) }
 }
 impl BitsetWord for u64 {
     #[inline(always)] fn one() -> Self { 1 }
     #[inline(always)] fn zero() -> Self { 0 }
+    #[inline(always)] fn trailing_zeros(self) -> u32 { u64::trailing_zeros(self) }
+    #[inline(always)] fn count_ones(self) -> u32 { u64::count_ones(self) }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct PrimitiveBitset<T: BitsetWord> {
     data: T,
 }
 
+/// Keys that changed between two matrix states
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BitsetDiff<T: BitsetWord> {
+    pub pressed: PrimitiveBitset<T>,
+    pub released: PrimitiveBitset<T>,
+}
+
+impl<T: BitsetWord> BitsetDiff<T> {
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.pressed.is_empty() && self.released.is_empty()
+    }
+}
+
 impl<T: BitsetWord> PrimitiveBitset<T> {
     #[inline(always)]
-    pub fn new(data: T) -> Self {
+    pub const fn new(data: T) -> Self {
         Self { data }
     }
 
@@ -131,5 +164,340 @@ impl<T: BitsetWord> PrimitiveBitset<T> {
     pub fn clear(&mut self) {
         self.data = T::zero();
     }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.data == T::zero()
+    }
+
+    #[inline(always)]
+    pub fn count_ones(&self) -> u32 {
+        self.data.count_ones()
+    }
+
+    /// Compares `self` against the previous state `prev`
+    #[inline(always)]
+    pub fn diff(&self, prev: &Self) -> BitsetDiff<T> {
+        BitsetDiff {
+            pressed: *self & !*prev,
+            released: *prev & !*self,
+        }
+    }
+
+    #[inline(always)]
+    pub fn iter_ones(&self) -> PrimitiveBitsetOnes<T> {
+        PrimitiveBitsetOnes { data: self.data }
+    }
+}
+
+impl<T: BitsetWord> BitAnd for PrimitiveBitset<T> {
+    type Output = Self;
+
+    #[inline(always)]
+    fn bitand(self, rhs: Self) -> Self {
+        Self::new(self.data & rhs.data)
+    }
 }
 
+impl<T: BitsetWord> BitOr for PrimitiveBitset<T> {
+    type Output = Self;
+
+    #[inline(always)]
+    fn bitor(self, rhs: Self) -> Self {
+        Self::new(self.data | rhs.data)
+    }
+}
+
+impl<T: BitsetWord> Not for PrimitiveBitset<T> {
+    type Output = Self;
+
+    #[inline(always)]
+    fn not(self) -> Self {
+        Self::new(!self.data)
+    }
+}
+
+pub struct PrimitiveBitsetOnes<T: BitsetWord> {
+    data: T,
+}
+
+impl<T: BitsetWord> Iterator for PrimitiveBitsetOnes<T> {
+    type Item = usize;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<usize> {
+        if self.data == T::zero() {
+            return None;
+        }
+        let idx = self.data.trailing_zeros() as usize;
+        self.data = self.data & !(T::one() << idx);
+        Some(idx)
+    }
+}
+
+
+const WORD_BITS: usize = u32::BITS as usize;
+
+/// Bitset backed by `N` 32-bit words, for matrices wider than a single primitive
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Bitset<const N: usize> {
+    words: [u32; N],
+}
+
+impl<const N: usize> Default for Bitset<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Bitset<N> {
+    pub const BITS: usize = N * WORD_BITS;
+
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Self { words: [0; N] }
+    }
+
+    #[inline(always)]
+    pub const fn from_words(words: [u32; N]) -> Self {
+        Self { words }
+    }
+
+    #[inline(always)]
+    pub fn words(&self) -> &[u32; N] {
+        &self.words
+    }
+
+    #[inline(always)]
+    pub fn set(&mut self, idx: usize, value: bool) {
+        let mask = 1 << (idx % WORD_BITS);
+        let word = &mut self.words[idx / WORD_BITS];
+        if value {
+            *word |= mask;
+        } else {
+            *word &= !mask;
+        }
+    }
+
+    #[inline(always)]
+    pub fn get(&self, idx: usize) -> bool {
+        (self.words[idx / WORD_BITS] >> (idx % WORD_BITS)) & 1 == 1
+    }
+
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.words = [0; N];
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    pub fn count_ones(&self) -> u32 {
+        self.words.iter().map(|w| w.count_ones()).sum()
+    }
+
+    /// Bits that differ between `self` and `other`
+    pub fn xor(&self, other: &Self) -> Self {
+        let mut result = *self;
+        for (a, b) in result.words.iter_mut().zip(other.words.iter()) {
+            *a ^= *b;
+        }
+        result
+    }
+
+    /// Sets every bit in `range` to `value`, the part of `range` past
+    /// [`Bitset::BITS`] is ignored
+    pub fn set_range(&mut self, range: Range<usize>, value: bool) {
+        for idx in range.start..range.end.min(Self::BITS) {
+            self.set(idx, value);
+        }
+    }
+
+    /// Returns `true` if any bit in `range` is set, the part of `range` past
+    /// [`Bitset::BITS`] is ignored
+    pub fn any_in_range(&self, range: Range<usize>) -> bool {
+        let end = range.end.min(Self::BITS);
+        let mut start = range.start;
+        // One masked word at a time, the first and last ones partially
+        while start < end {
+            let word = start / WORD_BITS;
+            let first = start % WORD_BITS;
+            let len = (end - word * WORD_BITS).min(WORD_BITS) - first;
+            let mask = (u32::MAX >> (WORD_BITS - len)) << first;
+            if self.words[word] & mask != 0 {
+                return true;
+            }
+            start = (word + 1) * WORD_BITS;
+        }
+        false
+    }
+
+    pub fn iter_ones(&self) -> BitsetOnes<'_, N> {
+        BitsetOnes {
+            words: &self.words,
+            word_idx: 0,
+            current: if N > 0 { self.words[0] } else { 0 },
+        }
+    }
+
+    /// Packs the bitset into 7-bit UART bytes, see [`packed_len`].
+    /// `out` must be at least `packed_len(bits)` long, extra bits are dropped
+    pub fn pack_7bit(&self, out: &mut [u8]) {
+        for (i, byte) in out.iter_mut().enumerate() {
+            let mut value = 0;
+            for bit in 0..7 {
+                let idx = i * 7 + bit;
+                if idx < Self::BITS && self.get(idx) {
+                    value |= 1 << bit;
+                }
+            }
+            *byte = value;
+        }
+        if let Some(first) = out.first_mut() {
+            *first |= 0x80;
+        }
+    }
+
+    /// Inverse of [`Bitset::pack_7bit`]
+    pub fn unpack_7bit(data: &[u8]) -> Self {
+        let mut result = Self::new();
+        for (i, byte) in data.iter().enumerate() {
+            for bit in 0..7 {
+                let idx = i * 7 + bit;
+                if idx < Self::BITS && (byte >> bit) & 1 == 1 {
+                    result.set(idx, true);
+                }
+            }
+        }
+        result
+    }
+}
+
+pub struct BitsetOnes<'a, const N: usize> {
+    words: &'a [u32; N],
+    word_idx: usize,
+    current: u32,
+}
+
+impl<const N: usize> Iterator for BitsetOnes<'_, N> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            if self.current != 0 {
+                let bit = self.current.trailing_zeros() as usize;
+                self.current &= self.current - 1;
+                return Some(self.word_idx * WORD_BITS + bit);
+            }
+            self.word_idx += 1;
+            if self.word_idx >= N {
+                return None;
+            }
+            self.current = self.words[self.word_idx];
+        }
+    }
+}
+
+/// Number of bytes needed to send `bits` bits over the split link.
+///
+/// Byte format:
+///  - [7] bit:
+///     - 1: It's the message first byte
+///     - 0: It's the message continuation byte
+///  - [0..7] bits: data
+pub const fn packed_len(bits: usize) -> usize {
+    bits.div_ceil(7)
+}
+
+/// Matrix state of one half as sent over the split link
+pub type MatrixBitset = Bitset<1>;
+
+/// Size of a single split link message
+pub const MATRIX_PACKET_LEN: usize = packed_len(MatrixBitset::BITS);
+
+/// Host keyboard LED state, bits follow the HID LED page order
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct HostLeds(pub u8);
+
+impl HostLeds {
+    pub const NUM_LOCK: u8 = 1 << 0;
+    pub const CAPS_LOCK: u8 = 1 << 1;
+    pub const SCROLL_LOCK: u8 = 1 << 2;
+    pub const COMPOSE: u8 = 1 << 3;
+    pub const KANA: u8 = 1 << 4;
+
+    #[inline(always)]
+    pub fn get(&self, led: u8) -> bool {
+        self.0 & led != 0
+    }
+}
+
+/// State of the main half sent to the right half as a single byte split
+/// link message, with the same start bit as the matrix messages
+///  - [0..5] bits: host LEDs
+///  - [5] bit: USB is suspended, the right half should scan in low-power mode
+///  - [6] bit: clear, set marks a [`MainHalfCommand`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct MainHalfStatus {
+    pub leds: HostLeds,
+    pub suspended: bool,
+}
+
+impl MainHalfStatus {
+    const LEDS_MASK: u8 = 0x1f;
+    const SUSPENDED: u8 = 1 << 5;
+
+    #[inline(always)]
+    pub fn pack(&self) -> u8 {
+        let suspended = if self.suspended { Self::SUSPENDED } else { 0 };
+        self.leds.0 & Self::LEDS_MASK | suspended | 0x80
+    }
+
+    #[inline(always)]
+    pub fn unpack(data: u8) -> Self {
+        Self {
+            leds: HostLeds(data & Self::LEDS_MASK),
+            suspended: data & Self::SUSPENDED != 0,
+        }
+    }
+}
+
+/// One-shot request of the main half, a single byte message with bits 6 and 7
+/// set and the command id in the low bits
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum MainHalfCommand {
+    /// Reboot into the ROM DFU bootloader
+    EnterBootloader = 0x01,
+}
+
+impl MainHalfCommand {
+    const MARK: u8 = 0xC0;
+
+    #[inline(always)]
+    pub fn pack(self) -> u8 {
+        self as u8 | Self::MARK
+    }
+}
+
+/// Single byte message from the main half
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MainHalfMessage {
+    Status(MainHalfStatus),
+    Command(MainHalfCommand),
+}
+
+impl MainHalfMessage {
+    /// `None` for bytes without the start bit and unknown commands
+    pub fn unpack(data: u8) -> Option<Self> {
+        match data & MainHalfCommand::MARK {
+            MainHalfCommand::MARK => match data & !MainHalfCommand::MARK {
+                0x01 => Some(MainHalfMessage::Command(MainHalfCommand::EnterBootloader)),
+                _ => None,
+            },
+            0x80 => Some(MainHalfMessage::Status(MainHalfStatus::unpack(data))),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Versions of the configuration the left half keeps in flash, the keymap
+//! page and the settings EEPROM (see [`crate::boot`] for the layout).
+//!
+//! Versions:
+//!  0. Settings in the EEPROM emulation without a version record, keymap
+//!     blob at [`KEYMAP_ADDRESS`]. The firmware before it stored nothing
+//!  1. The same with a [`keys::VERSION`] record
+//!
+//! A new version adds its migration from the previous one to [`migrate`].
+//!
+//! The keymap blob versions itself, see [`crate::keymap_blob`].
+//!
+//! Flash offsets count from [`FLASH_START`], as [`NorFlash`] offsets do on
+//! the device.
+
+use crate::boot::{FLASH_START, KEYMAP_ADDRESS, PAGE_LEN, SETTINGS_ADDRESS};
+use crate::eeprom::{Eeprom, Error};
+use embedded_storage::nor_flash::NorFlash;
+
+pub const VERSION: u8 = 1;
+
+/// Settings EEPROM keys, a key is never reused for another setting
+pub mod keys {
+    /// Version of the stored configuration
+    pub const VERSION: u8 = 0x00;
+    /// 0: NKRO, 1: 6KRO
+    pub const KEYBOARD_MODE: u8 = 0x01;
+}
+
+const KEYMAP: u32 = KEYMAP_ADDRESS - FLASH_START;
+const SETTINGS: u32 = SETTINGS_ADDRESS - FLASH_START;
+const SETTINGS_LEN: u32 = 2 * PAGE_LEN;
+
+/// Brings older configurations to [`VERSION`] and mounts the settings
+/// EEPROM. A configuration newer than this firmware is left as it is
+pub fn open<F: NorFlash>(flash: &mut F) -> Result<Eeprom, Error<F::Error>> {
+    let mut eeprom = Eeprom::mount(flash, SETTINGS, PAGE_LEN)?;
+    let mut record = [0; 1];
+    let mut version = match eeprom.read(flash, keys::VERSION, &mut record)? {
+        Some(1) => record[0],
+        // Blank or written before the version record
+        _ => 0,
+    };
+    if version > VERSION {
+        return Ok(eeprom);
+    }
+
+    while version < VERSION {
+        migrate(&mut eeprom, flash, version)?;
+        version += 1;
+    }
+    // A no-op once the record is there
+    eeprom.write(flash, keys::VERSION, &[VERSION])?;
+    Ok(eeprom)
+}
+
+/// Brings the configuration from `version` to the next one
+fn migrate<F: NorFlash>(
+    _eeprom: &mut Eeprom,
+    _flash: &mut F,
+    version: u8,
+) -> Result<(), Error<F::Error>> {
+    match version {
+        // Only the record is new, written by `open`
+        0 => Ok(()),
+        _ => unreachable!(),
+    }
+}
+
+/// Erases the keymap and the settings, the defaults load on the next
+/// [`open`]
+pub fn factory_reset<F: NorFlash>(flash: &mut F) -> Result<(), F::Error> {
+    flash.erase(KEYMAP, KEYMAP + PAGE_LEN)?;
+    flash.erase(SETTINGS, SETTINGS + SETTINGS_LEN)
+}
@@ -0,0 +1,118 @@
+//! VIA protocol (version 12) command ids and the mapping between QMK keycodes,
+//! which VIA uses for keymap entries, and [`RawKey`].
+//!
+//! Only the QMK basic keycode range and `QK_BOOTLOADER` are mapped. Consumer usages without a QMK
+//! keycode are reported as `KC_NO`.
+
+use crate::config_protocol::{KeyKind, RawKey};
+
+pub const PROTOCOL_VERSION: u16 = 0x000C;
+
+pub const GET_PROTOCOL_VERSION: u8 = 0x01;
+pub const GET_KEYBOARD_VALUE: u8 = 0x02;
+pub const SET_KEYBOARD_VALUE: u8 = 0x03;
+pub const DYNAMIC_KEYMAP_GET_KEYCODE: u8 = 0x04;
+pub const DYNAMIC_KEYMAP_SET_KEYCODE: u8 = 0x05;
+pub const DYNAMIC_KEYMAP_RESET: u8 = 0x06;
+pub const CUSTOM_SET_VALUE: u8 = 0x07;
+pub const CUSTOM_GET_VALUE: u8 = 0x08;
+pub const CUSTOM_SAVE: u8 = 0x09;
+pub const EEPROM_RESET: u8 = 0x0A;
+pub const BOOTLOADER_JUMP: u8 = 0x0B;
+pub const DYNAMIC_KEYMAP_MACRO_GET_COUNT: u8 = 0x0C;
+pub const DYNAMIC_KEYMAP_MACRO_GET_BUFFER_SIZE: u8 = 0x0D;
+pub const DYNAMIC_KEYMAP_MACRO_GET_BUFFER: u8 = 0x0E;
+pub const DYNAMIC_KEYMAP_MACRO_SET_BUFFER: u8 = 0x0F;
+pub const DYNAMIC_KEYMAP_MACRO_RESET: u8 = 0x10;
+pub const DYNAMIC_KEYMAP_GET_LAYER_COUNT: u8 = 0x11;
+pub const DYNAMIC_KEYMAP_GET_BUFFER: u8 = 0x12;
+pub const DYNAMIC_KEYMAP_SET_BUFFER: u8 = 0x13;
+/// Sent back in place of the command id of anything not handled
+pub const UNHANDLED: u8 = 0xFF;
+
+// `GET_KEYBOARD_VALUE`/`SET_KEYBOARD_VALUE` ids
+pub const UPTIME: u8 = 0x01;
+pub const LAYOUT_OPTIONS: u8 = 0x02;
+pub const SWITCH_MATRIX_STATE: u8 = 0x03;
+pub const FIRMWARE_VERSION: u8 = 0x04;
+pub const DEVICE_INDICATION: u8 = 0x05;
+
+/// Largest payload of the buffer commands: 64 bytes minus the 4 byte header
+pub const BUFFER_CHUNK_LEN: usize = 28;
+
+const KC_NO: u16 = 0x0000;
+
+/// QMK keycodes of the consumer, system, mouse and command keys, paired with
+/// the [`RawKey`] they stand for
+#[rustfmt::skip]
+const SPECIAL_KEYCODES: &[(u16, KeyKind, u16)] = &[
+    (0x00A5, KeyKind::System, 1),          // KC_SYSTEM_POWER
+    (0x00A6, KeyKind::System, 2),          // KC_SYSTEM_SLEEP
+    (0x00A7, KeyKind::System, 3),          // KC_SYSTEM_WAKE
+    (0x00A8, KeyKind::Consumer, 0x00E2),   // KC_AUDIO_MUTE
+    (0x00A9, KeyKind::Consumer, 0x00E9),   // KC_AUDIO_VOL_UP
+    (0x00AA, KeyKind::Consumer, 0x00EA),   // KC_AUDIO_VOL_DOWN
+    (0x00AB, KeyKind::Consumer, 0x00B5),   // KC_MEDIA_NEXT_TRACK
+    (0x00AC, KeyKind::Consumer, 0x00B6),   // KC_MEDIA_PREV_TRACK
+    (0x00AD, KeyKind::Consumer, 0x00B7),   // KC_MEDIA_STOP
+    (0x00AE, KeyKind::Consumer, 0x00CD),   // KC_MEDIA_PLAY_PAUSE
+    (0x00AF, KeyKind::Consumer, 0x0183),   // KC_MEDIA_SELECT
+    (0x00B0, KeyKind::Consumer, 0x00B8),   // KC_MEDIA_EJECT
+    (0x00B1, KeyKind::Consumer, 0x018A),   // KC_MAIL
+    (0x00B2, KeyKind::Consumer, 0x0192),   // KC_CALCULATOR
+    (0x00B3, KeyKind::Consumer, 0x0194),   // KC_MY_COMPUTER
+    (0x00B4, KeyKind::Consumer, 0x0221),   // KC_WWW_SEARCH
+    (0x00B5, KeyKind::Consumer, 0x0223),   // KC_WWW_HOME
+    (0x00B6, KeyKind::Consumer, 0x0224),   // KC_WWW_BACK
+    (0x00B7, KeyKind::Consumer, 0x0225),   // KC_WWW_FORWARD
+    (0x00B8, KeyKind::Consumer, 0x0226),   // KC_WWW_STOP
+    (0x00B9, KeyKind::Consumer, 0x0227),   // KC_WWW_REFRESH
+    (0x00BA, KeyKind::Consumer, 0x022A),   // KC_WWW_FAVORITES
+    (0x00BB, KeyKind::Consumer, 0x00B3),   // KC_MEDIA_FAST_FORWARD
+    (0x00BC, KeyKind::Consumer, 0x00B4),   // KC_MEDIA_REWIND
+    (0x00BD, KeyKind::Consumer, 0x006F),   // KC_BRIGHTNESS_UP
+    (0x00BE, KeyKind::Consumer, 0x0070),   // KC_BRIGHTNESS_DOWN
+    (0x00BF, KeyKind::Consumer, 0x019F),   // KC_CONTROL_PANEL
+    (0x00C0, KeyKind::Consumer, 0x01CB),   // KC_ASSISTANT
+    (0x00C1, KeyKind::Consumer, 0x029F),   // KC_MISSION_CONTROL
+    (0x00C2, KeyKind::Consumer, 0x02A0),   // KC_LAUNCHPAD
+    (0x00CD, KeyKind::MouseMove, 0),       // KC_MS_UP
+    (0x00CE, KeyKind::MouseMove, 1),       // KC_MS_DOWN
+    (0x00CF, KeyKind::MouseMove, 2),       // KC_MS_LEFT
+    (0x00D0, KeyKind::MouseMove, 3),       // KC_MS_RIGHT
+    (0x00D1, KeyKind::MouseButton, 0),     // KC_MS_BTN1
+    (0x00D2, KeyKind::MouseButton, 1),     // KC_MS_BTN2
+    (0x00D3, KeyKind::MouseButton, 2),     // KC_MS_BTN3
+    (0x00D4, KeyKind::MouseButton, 3),     // KC_MS_BTN4
+    (0x00D5, KeyKind::MouseButton, 4),     // KC_MS_BTN5
+    (0x00D9, KeyKind::MouseWheel, 0),      // KC_MS_WH_UP
+    (0x00DA, KeyKind::MouseWheel, 1),      // KC_MS_WH_DOWN
+    (0x00DB, KeyKind::MouseWheel, 2),      // KC_MS_WH_LEFT
+    (0x00DC, KeyKind::MouseWheel, 3),      // KC_MS_WH_RIGHT
+    (0x7C00, KeyKind::Command, 1),         // QK_BOOTLOADER
+];
+
+/// Keyboard usages QMK uses as-is as keycodes
+fn is_basic_keycode(code: u16) -> bool {
+    matches!(code, 0x0000..=0x00A4 | 0x00E0..=0x00E7)
+}
+
+pub fn keycode_to_raw(keycode: u16) -> Option<RawKey> {
+    if is_basic_keycode(keycode) {
+        return Some(RawKey::new(KeyKind::Keyboard, keycode));
+    }
+    SPECIAL_KEYCODES
+        .iter()
+        .find(|(qmk, _, _)| *qmk == keycode)
+        .map(|&(_, kind, code)| RawKey::new(kind, code))
+}
+
+pub fn raw_to_keycode(raw: RawKey) -> u16 {
+    if raw.kind == KeyKind::Keyboard && is_basic_keycode(raw.code) {
+        return raw.code;
+    }
+    SPECIAL_KEYCODES
+        .iter()
+        .find(|&&(_, kind, code)| kind == raw.kind && code == raw.code)
+        .map_or(KC_NO, |&(qmk, _, _)| qmk)
+}
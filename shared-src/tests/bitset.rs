@@ -0,0 +1,167 @@
+use shared_src::{packed_len, Bitset, PrimitiveBitset};
+
+/// Bits set at the edges of the 7-bit bytes and of the 32-bit words
+const EDGES: &[usize] = &[0, 1, 6, 7, 13, 14, 31, 32, 34, 35, 63, 64, 69, 70, 95];
+
+fn with_bits<const N: usize>(bits: &[usize]) -> Bitset<N> {
+    let mut bitset = Bitset::new();
+    for &idx in bits {
+        bitset.set(idx, true);
+    }
+    bitset
+}
+
+#[test]
+fn pack_round_trip_keeps_every_single_bit() {
+    let mut packed = [0; packed_len(Bitset::<3>::BITS)];
+    for idx in 0..Bitset::<3>::BITS {
+        let bitset: Bitset<3> = with_bits(&[idx]);
+        bitset.pack_7bit(&mut packed);
+        assert_eq!(Bitset::<3>::unpack_7bit(&packed), bitset, "bit {idx}");
+    }
+}
+
+#[test]
+fn pack_round_trip_across_byte_and_word_edges() {
+    let mut packed = [0; packed_len(Bitset::<3>::BITS)];
+    for bitset in [
+        with_bits::<3>(EDGES),
+        Bitset::from_words([u32::MAX; 3]),
+        Bitset::new(),
+    ] {
+        bitset.pack_7bit(&mut packed);
+        assert_eq!(Bitset::<3>::unpack_7bit(&packed), bitset);
+    }
+}
+
+#[test]
+fn only_the_first_packed_byte_has_the_start_bit() {
+    let mut packed = [0; packed_len(Bitset::<3>::BITS)];
+    // Bit 7 of the data is the first bit of the second byte, not its start
+    // bit
+    with_bits::<3>(&[6, 7]).pack_7bit(&mut packed);
+    assert_eq!(packed[0], 0x80 | 1 << 6);
+    assert_eq!(packed[1], 1);
+    assert!(packed[2..].iter().all(|&byte| byte == 0));
+
+    Bitset::<3>::from_words([u32::MAX; 3]).pack_7bit(&mut packed);
+    assert_eq!(packed[0], 0xFF);
+    assert!(packed[1..].iter().all(|&byte| byte & 0x80 == 0));
+}
+
+#[test]
+fn unpack_ignores_the_start_bit() {
+    assert_eq!(Bitset::<1>::unpack_7bit(&[0x80, 0, 0, 0, 0]), Bitset::new());
+    assert_eq!(
+        Bitset::<1>::unpack_7bit(&[0x81, 0, 0, 0, 0]),
+        with_bits(&[0])
+    );
+}
+
+#[test]
+fn pack_drops_bits_beyond_the_buffer_and_unpack_beyond_the_set() {
+    let mut packed = [0; 2];
+    with_bits::<3>(&[13, 14]).pack_7bit(&mut packed);
+    assert_eq!(Bitset::<3>::unpack_7bit(&packed), with_bits(&[13]));
+
+    // The last byte of a 32-bit set carries 4 bits, the rest are dropped
+    let mut packed = [0; packed_len(32)];
+    packed[4] = 0x7F;
+    assert_eq!(
+        Bitset::<1>::unpack_7bit(&packed),
+        with_bits(&[28, 29, 30, 31])
+    );
+}
+
+#[test]
+fn iter_ones_in_order_across_words() {
+    assert_eq!(with_bits::<3>(EDGES).iter_ones().collect::<Vec<_>>(), EDGES);
+    assert_eq!(Bitset::<3>::new().iter_ones().next(), None);
+    assert_eq!(Bitset::<0>::new().iter_ones().next(), None);
+    // Empty words before and after
+    assert_eq!(with_bits::<3>(&[40]).iter_ones().collect::<Vec<_>>(), [40]);
+    assert_eq!(
+        Bitset::<3>::from_words([u32::MAX; 3]).iter_ones().count(),
+        96
+    );
+}
+
+#[test]
+fn set_range_across_words() {
+    let mut bitset = Bitset::<3>::new();
+    bitset.set_range(30..66, true);
+    assert_eq!(
+        bitset.iter_ones().collect::<Vec<_>>(),
+        (30..66).collect::<Vec<_>>()
+    );
+
+    bitset.set_range(31..65, false);
+    assert_eq!(bitset.iter_ones().collect::<Vec<_>>(), [30, 65]);
+
+    // Empty and whole ranges
+    bitset.set_range(40..40, true);
+    assert_eq!(bitset.count_ones(), 2);
+    bitset.set_range(0..96, true);
+    assert_eq!(bitset, Bitset::from_words([u32::MAX; 3]));
+    bitset.set_range(0..96, false);
+    assert!(bitset.is_empty());
+}
+
+#[test]
+fn any_in_range_at_the_range_ends() {
+    let bitset: Bitset<3> = with_bits(&[31, 64]);
+    assert!(bitset.any_in_range(31..32));
+    assert!(bitset.any_in_range(0..32));
+    assert!(!bitset.any_in_range(0..31));
+    assert!(!bitset.any_in_range(32..64));
+    assert!(bitset.any_in_range(32..65));
+    assert!(bitset.any_in_range(64..96));
+    assert!(!bitset.any_in_range(65..96));
+    assert!(!bitset.any_in_range(40..40));
+    // Clamped to the set
+    assert!(!bitset.any_in_range(65..1000));
+    assert!(bitset.any_in_range(0..1000));
+    assert!(!Bitset::<3>::from_words([u32::MAX; 3]).any_in_range(96..100));
+}
+
+#[test]
+fn primitive_iter_ones_and_diff() {
+    let all = PrimitiveBitset::new(u32::MAX);
+    assert_eq!(
+        all.iter_ones().collect::<Vec<_>>(),
+        (0..32).collect::<Vec<_>>()
+    );
+    assert_eq!(PrimitiveBitset::new(0u32).iter_ones().next(), None);
+    assert_eq!(
+        PrimitiveBitset::new(1u32 << 31 | 1)
+            .iter_ones()
+            .collect::<Vec<_>>(),
+        [0, 31]
+    );
+
+    let prev = PrimitiveBitset::new(0b0110u32);
+    let diff = PrimitiveBitset::new(0b1100u32).diff(&prev);
+    assert_eq!(diff.pressed.iter_ones().collect::<Vec<_>>(), [3]);
+    assert_eq!(diff.released.iter_ones().collect::<Vec<_>>(), [1]);
+
+    let same = prev.diff(&prev);
+    assert!(same.is_empty());
+}
+
+#[test]
+fn ranges_up_to_and_past_the_end() {
+    let bits = Bitset::<3>::BITS;
+    let mut bitset = Bitset::<3>::new();
+    bitset.set_range(90..bits, true);
+    assert_eq!(bitset.count_ones(), 6);
+    assert!(bitset.any_in_range(95..bits));
+
+    // Clamped like `any_in_range`
+    bitset.set_range(94..bits + 10, false);
+    assert_eq!(bitset.iter_ones().collect::<Vec<_>>(), [90, 91, 92, 93]);
+    bitset.set_range(bits..bits + 10, true);
+    assert_eq!(bitset.count_ones(), 4);
+    assert!(!bitset.any_in_range(94..bits + 10));
+    assert!(bitset.any_in_range(93..bits + 10));
+    assert!(!bitset.any_in_range(bits..bits + 10));
+}
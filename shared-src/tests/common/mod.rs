@@ -0,0 +1,133 @@
+//! Flash simulation shared by the storage tests
+
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+
+pub const ERASE_LEN: usize = 256;
+
+/// In-memory NOR flash with half-word programming like the STM32F1, which
+/// loses power after a given number of operations
+pub struct MockFlash {
+    pub data: Vec<u8>,
+    /// Half-word writes and page erases left before the power cut
+    pub budget: Option<usize>,
+    pub powered: bool,
+    pub operations: usize,
+    /// Complete erases of every erase unit
+    pub erases: Vec<usize>,
+}
+
+#[allow(dead_code)]
+impl MockFlash {
+    pub fn new(len: usize) -> Self {
+        Self {
+            data: vec![0xFF; len],
+            budget: None,
+            powered: true,
+            operations: 0,
+            erases: vec![0; len / ERASE_LEN],
+        }
+    }
+
+    /// Power comes back, the contents stay
+    pub fn restart(&mut self) {
+        self.budget = None;
+        self.powered = true;
+    }
+
+    /// Spends one operation. On the last one the power is cut, `variant`
+    /// says how much of it still makes it to the flash
+    fn spend(&mut self) -> Option<usize> {
+        if !self.powered {
+            return Some(0);
+        }
+        self.operations += 1;
+        match &mut self.budget {
+            Some(0) => {
+                self.powered = false;
+                Some(self.operations % 3)
+            }
+            Some(budget) => {
+                *budget -= 1;
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+impl ErrorType for MockFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for MockFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        if !self.powered {
+            return Err(NorFlashErrorKind::Other);
+        }
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl NorFlash for MockFlash {
+    const WRITE_SIZE: usize = 2;
+    const ERASE_SIZE: usize = ERASE_LEN;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        assert!(from.is_multiple_of(ERASE_LEN as u32) && to.is_multiple_of(ERASE_LEN as u32));
+        for page in (from..to).step_by(ERASE_LEN) {
+            let start = page as usize;
+            let end = start + ERASE_LEN;
+            match self.spend() {
+                None => {
+                    self.data[start..end].fill(0xFF);
+                    self.erases[page as usize / ERASE_LEN] += 1;
+                }
+                // Half erased page, either half
+                Some(variant) => {
+                    let middle = start + ERASE_LEN / 2;
+                    if variant % 2 == 0 {
+                        self.data[start..middle].fill(0xFF);
+                    } else {
+                        self.data[middle..end].fill(0xFF);
+                    }
+                    return Err(NorFlashErrorKind::Other);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        assert!(offset.is_multiple_of(2) && bytes.len().is_multiple_of(2));
+        for (i, half_word) in bytes.chunks(2).enumerate() {
+            let at = offset as usize + 2 * i;
+            assert_eq!(
+                self.data[at..at + 2],
+                [0xFF, 0xFF],
+                "programming a half-word that isn't erased at {at:#x}"
+            );
+            match self.spend() {
+                None => self.data[at..at + 2].copy_from_slice(half_word),
+                Some(variant) => {
+                    // Nothing, one byte or everything programmed
+                    if variant >= 1 {
+                        self.data[at] = half_word[0];
+                    }
+                    if variant == 2 {
+                        self.data[at + 1] = half_word[1];
+                    }
+                    return Err(NorFlashErrorKind::Other);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use shared_src::config_protocol::{
+    Command, DiagnosticCounters, FirmwareVersion, KeyKind, KeyPosition, RawKey, Report, Request,
+    Response, Status, REPORT_LEN,
+};
+
+const POSITION: KeyPosition = KeyPosition {
+    side: 1,
+    layer: 4,
+    index: 29,
+};
+
+fn requests() -> [Request; 7] {
+    [
+        Request::GetVersion,
+        Request::GetKeymapEntry(POSITION),
+        Request::SetKeymapEntry(POSITION, RawKey::new(KeyKind::Consumer, 0x00CD)),
+        Request::GetMatrix,
+        Request::GetCounters,
+        Request::RebootToBootloader(0),
+        Request::RebootToBootloader(1),
+    ]
+}
+
+fn written(request: Request) -> Report {
+    let mut report = [0xAA; REPORT_LEN];
+    request.write(&mut report);
+    report
+}
+
+fn round_trip(command: Command, response: Response) -> Result<Response, Status> {
+    let mut report = [0xAA; REPORT_LEN];
+    response.write(command as u8, &mut report);
+    Response::parse(command, &report)
+}
+
+#[test]
+fn every_request_round_trips() {
+    for request in requests() {
+        assert_eq!(Request::parse(&written(request)), Ok(request));
+    }
+}
+
+#[test]
+fn every_key_kind_round_trips() {
+    for kind in 0x00..=0x06 {
+        let key = RawKey::new(KeyKind::try_from(kind).unwrap(), 0xBEEF);
+        let request = Request::SetKeymapEntry(POSITION, key);
+        assert_eq!(Request::parse(&written(request)), Ok(request));
+        assert_eq!(
+            round_trip(Command::GetKeymapEntry, Response::KeymapEntry(key)),
+            Ok(Response::KeymapEntry(key))
+        );
+    }
+}
+
+#[test]
+fn request_layout_is_stable() {
+    let report = written(Request::SetKeymapEntry(
+        POSITION,
+        RawKey::new(KeyKind::Consumer, 0x00CD),
+    ));
+    assert_eq!(report[..7], [0x42, 1, 4, 29, 0x01, 0xCD, 0x00]);
+    // The rest of the report is cleared
+    assert!(report[7..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn every_response_round_trips() {
+    let counters = DiagnosticCounters {
+        link_packets: 1,
+        link_errors: 0x0202_0202,
+        reports_sent: u32::MAX,
+        report_retries: 4,
+        rollovers: 0x8000_0000,
+    };
+    for (command, response) in [
+        (
+            Command::GetVersion,
+            Response::Version {
+                protocol: 2,
+                firmware: FirmwareVersion {
+                    major: 1,
+                    minor: 22,
+                    patch: 255,
+                },
+            },
+        ),
+        (
+            Command::GetKeymapEntry,
+            Response::KeymapEntry(RawKey::new(KeyKind::Keyboard, 0x04)),
+        ),
+        (
+            Command::GetMatrix,
+            Response::Matrix {
+                left: 0x3FFF_FFFF,
+                right: 1 << 29,
+            },
+        ),
+        (Command::GetCounters, Response::Counters(counters)),
+        (Command::SetKeymapEntry, Response::Done),
+        (Command::RebootToBootloader, Response::Done),
+    ] {
+        assert_eq!(round_trip(command, response), Ok(response));
+    }
+}
+
+#[test]
+fn error_responses_round_trip() {
+    for status in [
+        Status::UnknownCommand,
+        Status::InvalidArgument,
+        Status::Unsupported,
+    ] {
+        let response = Response::Error(status);
+        for command in [
+            Command::GetVersion,
+            Command::GetKeymapEntry,
+            Command::GetCounters,
+        ] {
+            assert_eq!(round_trip(command, response), Ok(response));
+        }
+    }
+}
+
+#[test]
+fn unknown_command_is_rejected() {
+    let mut report = [0; REPORT_LEN];
+    for command in [Command::FIRST - 1, Command::LAST + 1, 0xFF] {
+        report[0] = command;
+        assert_eq!(Request::parse(&report), Err(Status::UnknownCommand));
+    }
+}
+
+#[test]
+fn truncated_report_is_rejected() {
+    for request in requests() {
+        let report = written(request);
+        assert_eq!(
+            Request::parse(&report[..REPORT_LEN - 1]),
+            Err(Status::InvalidArgument)
+        );
+    }
+    assert_eq!(Request::parse(&[]), Err(Status::InvalidArgument));
+
+    let mut report = [0; REPORT_LEN];
+    Response::Done.write(Command::GetVersion as u8, &mut report);
+    assert_eq!(
+        Response::parse(Command::GetVersion, &report[..8]),
+        Err(Status::InvalidArgument)
+    );
+}
+
+#[test]
+fn out_of_range_key_kind_is_rejected() {
+    let mut report = written(Request::SetKeymapEntry(
+        POSITION,
+        RawKey::new(KeyKind::Keyboard, 0x04),
+    ));
+    report[4] = 0x07;
+    assert_eq!(Request::parse(&report), Err(Status::InvalidArgument));
+
+    let mut report = [0; REPORT_LEN];
+    Response::KeymapEntry(RawKey::new(KeyKind::Keyboard, 0x04))
+        .write(Command::GetKeymapEntry as u8, &mut report);
+    report[2] = 0x7F;
+    assert_eq!(
+        Response::parse(Command::GetKeymapEntry, &report),
+        Err(Status::InvalidArgument)
+    );
+}
+
+#[test]
+fn response_to_another_command_is_rejected() {
+    let mut report = [0; REPORT_LEN];
+    Response::Done.write(Command::SetKeymapEntry as u8, &mut report);
+    assert_eq!(
+        Response::parse(Command::GetVersion, &report),
+        Err(Status::InvalidArgument)
+    );
+
+    // Unknown status
+    report[1] = 0x04;
+    assert_eq!(
+        Response::parse(Command::SetKeymapEntry, &report),
+        Err(Status::InvalidArgument)
+    );
+}
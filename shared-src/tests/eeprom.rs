@@ -0,0 +1,180 @@
+mod common;
+
+use common::MockFlash;
+use shared_src::eeprom::{Eeprom, Error, MAX_VALUE_LEN};
+
+const PAGE_LEN: u32 = common::ERASE_LEN as u32;
+/// Pages before the emulation, to catch offset mistakes
+const FIRST_PAGE: u32 = PAGE_LEN;
+
+fn mount(flash: &mut MockFlash) -> Eeprom {
+    Eeprom::mount(flash, FIRST_PAGE, PAGE_LEN).unwrap()
+}
+
+fn read(eeprom: &Eeprom, flash: &mut MockFlash, key: u8) -> Option<Vec<u8>> {
+    let mut value = [0; MAX_VALUE_LEN];
+    let len = eeprom.read(flash, key, &mut value).unwrap()?;
+    Some(value[..len].to_vec())
+}
+
+/// Writes that fill the pages several times over, with keys of different
+/// value lengths
+fn workload() -> Vec<(u8, Vec<u8>)> {
+    (0..60u8)
+        .map(|i| {
+            let key = i % 5;
+            let len = [1, 2, 5, 0, 12][key as usize];
+            (key, vec![i; len])
+        })
+        .collect()
+}
+
+#[test]
+fn empty_flash_reads_nothing() {
+    let mut flash = MockFlash::new(4 * PAGE_LEN as usize);
+    let eeprom = mount(&mut flash);
+    assert_eq!(read(&eeprom, &mut flash, 0), None);
+    assert_eq!(read(&eeprom, &mut flash, 0xFE), None);
+}
+
+#[test]
+fn values_survive_remount() {
+    let mut flash = MockFlash::new(4 * PAGE_LEN as usize);
+    let mut eeprom = mount(&mut flash);
+    eeprom.write(&mut flash, 1, &[1, 2, 3]).unwrap();
+    eeprom.write(&mut flash, 2, &[]).unwrap();
+    eeprom.write(&mut flash, 1, &[4]).unwrap();
+
+    let eeprom = mount(&mut flash);
+    assert_eq!(read(&eeprom, &mut flash, 1), Some(vec![4]));
+    assert_eq!(read(&eeprom, &mut flash, 2), Some(vec![]));
+    assert_eq!(read(&eeprom, &mut flash, 3), None);
+}
+
+#[test]
+fn compaction_keeps_latest_values() {
+    let mut flash = MockFlash::new(4 * PAGE_LEN as usize);
+    let mut eeprom = mount(&mut flash);
+    let mut expected = [None, None, None, None, None];
+    for (key, value) in workload() {
+        eeprom.write(&mut flash, key, &value).unwrap();
+        expected[key as usize] = Some(value);
+        for (key, value) in expected.iter().enumerate() {
+            assert_eq!(read(&eeprom, &mut flash, key as u8), *value);
+        }
+    }
+
+    let eeprom = mount(&mut flash);
+    for (key, value) in expected.iter().enumerate() {
+        assert_eq!(read(&eeprom, &mut flash, key as u8), *value);
+    }
+}
+
+#[test]
+fn pages_wear_evenly() {
+    let mut flash = MockFlash::new(4 * PAGE_LEN as usize);
+    let mut eeprom = mount(&mut flash);
+    for i in 0..2000u32 {
+        eeprom
+            .write(&mut flash, (i % 3) as u8, &i.to_le_bytes())
+            .unwrap();
+    }
+    let erases = &flash.erases[1..3];
+    assert!(erases[0] > 10);
+    assert!(erases[0].abs_diff(erases[1]) <= 1, "{erases:?}");
+    assert_eq!(flash.erases[0] + flash.erases[3], 0);
+}
+
+#[test]
+fn unchanged_value_isnt_rewritten() {
+    let mut flash = MockFlash::new(4 * PAGE_LEN as usize);
+    let mut eeprom = mount(&mut flash);
+    eeprom.write(&mut flash, 7, &[7; 4]).unwrap();
+    let operations = flash.operations;
+    eeprom.write(&mut flash, 7, &[7; 4]).unwrap();
+    assert_eq!(flash.operations, operations);
+}
+
+#[test]
+fn invalid_writes_are_rejected() {
+    let mut flash = MockFlash::new(4 * PAGE_LEN as usize);
+    let mut eeprom = mount(&mut flash);
+    assert_eq!(eeprom.write(&mut flash, 0xFF, &[0]), Err(Error::InvalidKey));
+    assert_eq!(
+        eeprom.write(&mut flash, 0, &[0; MAX_VALUE_LEN + 1]),
+        Err(Error::ValueTooLong)
+    );
+}
+
+#[test]
+fn too_many_keys_fill_the_page() {
+    let mut flash = MockFlash::new(4 * PAGE_LEN as usize);
+    let mut eeprom = mount(&mut flash);
+    let result = (0..=0xFE)
+        .map(|key| eeprom.write(&mut flash, key, &[key; 8]))
+        .find(Result::is_err);
+    assert_eq!(result, Some(Err(Error::Full)));
+
+    // What was stored before stays readable
+    let eeprom = mount(&mut flash);
+    assert_eq!(read(&eeprom, &mut flash, 0), Some(vec![0; 8]));
+}
+
+/// Cuts the power after every possible number of operations of the
+/// workload, then checks that every key holds either its value before the
+/// interrupted write or the one being written, and that writing still works
+#[test]
+fn power_cut_keeps_old_or_new_value() {
+    let total = {
+        let mut flash = MockFlash::new(4 * PAGE_LEN as usize);
+        let mut eeprom = mount(&mut flash);
+        for (key, value) in workload() {
+            eeprom.write(&mut flash, key, &value).unwrap();
+        }
+        flash.operations
+    };
+
+    for cut in 0..total {
+        let mut flash = MockFlash::new(4 * PAGE_LEN as usize);
+        flash.budget = Some(cut);
+        let mut committed: Vec<Option<Vec<u8>>> = vec![None; 5];
+        let mut interrupted = None;
+        if let Ok(mut eeprom) = Eeprom::mount(&mut flash, FIRST_PAGE, PAGE_LEN) {
+            for (key, value) in workload() {
+                if eeprom.write(&mut flash, key, &value).is_err() {
+                    interrupted = Some((key, value));
+                    break;
+                }
+                committed[key as usize] = Some(value);
+            }
+        }
+        assert!(!flash.powered, "cut {cut} never happened");
+
+        flash.restart();
+        let mut eeprom = mount(&mut flash);
+        for (key, old) in committed.iter().enumerate() {
+            let value = read(&eeprom, &mut flash, key as u8);
+            let new = match &interrupted {
+                Some((interrupted_key, new)) if *interrupted_key == key as u8 => Some(new),
+                _ => None,
+            };
+            assert!(
+                value == *old || value.as_ref() == new,
+                "cut {cut}: key {key} reads {value:?}, expected {old:?} or {new:?}"
+            );
+        }
+
+        // Still usable, including the compactions the writes trigger
+        for i in 0..40u8 {
+            eeprom.write(&mut flash, i % 5, &[cut as u8, i]).unwrap();
+        }
+        let eeprom = mount(&mut flash);
+        for key in 0..5u8 {
+            assert_eq!(
+                read(&eeprom, &mut flash, key),
+                Some(vec![cut as u8, 35 + key]),
+                "cut {cut}"
+            );
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use shared_src::config_protocol::{KeyKind, RawKey};
+use shared_src::keycodes::{self, ParseError};
+use shared_src::via;
+
+const KINDS: [KeyKind; 7] = [
+    KeyKind::Keyboard,
+    KeyKind::Consumer,
+    KeyKind::System,
+    KeyKind::MouseMove,
+    KeyKind::MouseButton,
+    KeyKind::MouseWheel,
+    KeyKind::Command,
+];
+
+fn table(kind: KeyKind) -> &'static [(&'static str, u16)] {
+    match kind {
+        KeyKind::Keyboard => keycodes::KEYBOARD,
+        KeyKind::Consumer => keycodes::CONSUMER,
+        KeyKind::System => keycodes::SYSTEM,
+        KeyKind::MouseMove | KeyKind::MouseWheel => keycodes::MOUSE_DIRECTION,
+        KeyKind::MouseButton => keycodes::MOUSE_BUTTON,
+        KeyKind::Command => keycodes::COMMAND,
+    }
+}
+
+/// Every named entry with the name of its table
+fn named_keys() -> impl Iterator<Item = (RawKey, &'static str)> {
+    KINDS.into_iter().flat_map(|kind| {
+        table(kind)
+            .iter()
+            .map(move |&(name, code)| (RawKey::new(kind, code), name))
+    })
+}
+
+#[test]
+fn every_name_round_trips() {
+    for (key, variant) in named_keys() {
+        let name = keycodes::name(key).unwrap();
+        assert_eq!(name.variant, variant, "{key:?}");
+        // As the keymap file spells it, short name or macro
+        assert_eq!(keycodes::parse(&name.to_string()), Ok(key), "{name}");
+    }
+}
+
+#[test]
+fn aliases_name_known_keys() {
+    for &(alias, variant) in keycodes::KEYBOARD_ALIASES {
+        let key = keycodes::parse(alias).unwrap();
+        assert_eq!(keycodes::name(key).unwrap().variant, variant);
+        assert_eq!(keycodes::name(key).unwrap().to_string(), alias);
+    }
+}
+
+#[test]
+fn unknown_names_are_rejected() {
+    assert_eq!(keycodes::parse("NoSuchKey"), Err(ParseError::UnknownKey));
+    assert_eq!(keycodes::parse("consumer(Q)"), Err(ParseError::UnknownKey));
+    assert_eq!(
+        keycodes::parse("lighting(On)"),
+        Err(ParseError::UnknownKind)
+    );
+    // Keyboard keys are written bare
+    assert_eq!(keycodes::parse("key(A)"), Err(ParseError::UnknownKind));
+    assert_eq!(keycodes::name(RawKey::new(KeyKind::Command, 9)), None);
+}
+
+#[test]
+fn qmk_basic_range_maps_both_ways() {
+    for keycode in (0x0000..=0x00A4).chain(0x00E0..=0x00E7) {
+        let key = RawKey::new(KeyKind::Keyboard, keycode);
+        assert_eq!(via::keycode_to_raw(keycode), Some(key));
+        assert_eq!(via::raw_to_keycode(key), keycode);
+    }
+}
+
+#[test]
+fn qmk_consumer_range_maps_both_ways() {
+    for (keycode, usage) in [
+        (0x00A8, 0x00E2), // KC_AUDIO_MUTE
+        (0x00A9, 0x00E9), // KC_AUDIO_VOL_UP
+        (0x00AE, 0x00CD), // KC_MEDIA_PLAY_PAUSE
+        (0x00B2, 0x0192), // KC_CALCULATOR
+        (0x00C2, 0x02A0), // KC_LAUNCHPAD
+    ] {
+        let key = RawKey::new(KeyKind::Consumer, usage);
+        assert_eq!(via::keycode_to_raw(keycode), Some(key));
+        assert_eq!(via::raw_to_keycode(key), keycode);
+    }
+    for keycode in 0x00A8..=0x00C2 {
+        let key = via::keycode_to_raw(keycode).unwrap();
+        assert_eq!(key.kind, KeyKind::Consumer, "{keycode:#06X}");
+        assert_eq!(via::raw_to_keycode(key), keycode);
+    }
+}
+
+#[test]
+fn every_named_key_with_a_qmk_keycode_maps_back() {
+    for (key, _) in named_keys() {
+        match via::raw_to_keycode(key) {
+            // Every named keyboard usage has a keycode, KC_NO is usage 0
+            0 if key.kind == KeyKind::Keyboard => assert_eq!(key.code, 0),
+            0 => {}
+            keycode => assert_eq!(via::keycode_to_raw(keycode), Some(key), "{key:?}"),
+        }
+    }
+}
+
+#[test]
+fn keys_without_a_qmk_keycode_read_kc_no() {
+    for key in [
+        RawKey::new(KeyKind::Consumer, 0x0196), // ALInternetBrowser
+        RawKey::new(KeyKind::Command, 0),       // ToggleKeyboardMode
+        RawKey::new(KeyKind::Keyboard, 0x00A5),
+    ] {
+        assert_eq!(via::raw_to_keycode(key), 0);
+    }
+}
+
+#[test]
+fn unknown_qmk_keycodes_map_to_nothing() {
+    for keycode in (0x00C3..=0x00CC)
+        .chain(0x00D6..=0x00D8)
+        .chain(0x00DD..=0x00DF)
+        .chain(0x00E8..=0x00FF)
+        .chain([0x0100, 0x5220, 0x7C01, 0xFFFF])
+    {
+        assert_eq!(via::keycode_to_raw(keycode), None, "{keycode:#06X}");
+    }
+}
@@ -0,0 +1,156 @@
+use shared_src::config_protocol::{KeyKind, RawKey};
+use shared_src::keymap_blob::{self, KeymapShape};
+
+/// Shape of the firmware the blobs below are loaded by
+const FIRMWARE: KeymapShape = KeymapShape {
+    left_layers: 5,
+    right_layers: 5,
+    layer_len: 30,
+};
+
+/// A key telling where it was stored
+fn key_at((side, layer, index): (u8, u8, u8)) -> RawKey {
+    RawKey::new(
+        KeyKind::Keyboard,
+        u16::from(side) << 12 | u16::from(layer) << 8 | u16::from(index),
+    )
+}
+
+fn encode(shape: KeymapShape) -> Vec<u8> {
+    let keys = (0..shape.entry_count()).map(|i| key_at(shape.position(i)));
+    let mut blob = vec![0xFF; shape.blob_len()];
+    assert_eq!(
+        keymap_blob::encode(shape, keys, &mut blob),
+        Some(blob.len())
+    );
+    blob
+}
+
+/// Positions and keys of a decoded blob that the firmware has room for
+fn loaded(blob: &[u8]) -> Vec<((u8, u8, u8), RawKey)> {
+    let (shape, entries) = keymap_blob::decode(blob).unwrap();
+    entries
+        .enumerate()
+        .map(|(i, key)| (shape.position(i), key.unwrap()))
+        .filter(|&((side, layer, index), _)| {
+            let layers = [FIRMWARE.left_layers, FIRMWARE.right_layers][side as usize];
+            layer < layers && index < FIRMWARE.layer_len
+        })
+        .collect()
+}
+
+#[test]
+fn full_keymap_round_trip() {
+    let blob = encode(FIRMWARE);
+    let (shape, entries) = keymap_blob::decode(&blob).unwrap();
+    assert_eq!(shape, FIRMWARE);
+
+    let entries: Vec<_> = entries.collect();
+    assert_eq!(entries.len(), 2 * 5 * 30);
+    for (i, key) in entries.into_iter().enumerate() {
+        assert_eq!(key, Some(key_at(FIRMWARE.position(i))));
+    }
+}
+
+#[test]
+fn encoded_form_is_stable() {
+    let shape = KeymapShape {
+        left_layers: 1,
+        right_layers: 1,
+        layer_len: 2,
+    };
+    let keys = [
+        RawKey::new(KeyKind::Keyboard, 0x04),
+        RawKey::new(KeyKind::Consumer, 0x00CD),
+        RawKey::new(KeyKind::Command, 1),
+        RawKey::new(KeyKind::MouseButton, 2),
+    ];
+    let mut blob = [0; 22];
+    assert_eq!(keymap_blob::encode(shape, keys, &mut blob), Some(22));
+    assert_eq!(
+        blob,
+        [
+            0x4D, 0x4B, 0x01, 0x01, 0x01, 0x02, // header
+            0x00, 0x04, 0x00, 0x01, 0xCD, 0x00, // left layer
+            0x06, 0x01, 0x00, 0x04, 0x02, 0x00, // right layer
+            0xB8, 0x2A, 0x36, 0xB4, // CRC-32
+        ]
+    );
+}
+
+#[test]
+fn encode_needs_room_and_every_key() {
+    let mut blob = vec![0; FIRMWARE.blob_len() - 1];
+    let keys = (0..FIRMWARE.entry_count()).map(|i| key_at(FIRMWARE.position(i)));
+    assert_eq!(keymap_blob::encode(FIRMWARE, keys, &mut blob), None);
+
+    let mut blob = vec![0; FIRMWARE.blob_len()];
+    let keys = (1..FIRMWARE.entry_count()).map(|i| key_at(FIRMWARE.position(i)));
+    assert_eq!(keymap_blob::encode(FIRMWARE, keys, &mut blob), None);
+}
+
+#[test]
+fn flipped_crc_byte_is_rejected() {
+    let mut blob = encode(FIRMWARE);
+    let last = blob.len() - 1;
+    blob[last] ^= 0x01;
+    assert!(keymap_blob::decode(&blob).is_none());
+}
+
+#[test]
+fn flipped_entry_byte_is_rejected() {
+    let mut blob = encode(FIRMWARE);
+    blob[10] ^= 0x40;
+    assert!(keymap_blob::decode(&blob).is_none());
+}
+
+#[test]
+fn bad_magic_is_rejected() {
+    let mut blob = encode(FIRMWARE);
+    blob[0] = 0x4C;
+    assert!(keymap_blob::decode(&blob).is_none());
+    // An erased page
+    assert!(keymap_blob::decode(&[0xFF; 1024]).is_none());
+}
+
+#[test]
+fn other_format_version_is_rejected() {
+    let mut blob = encode(FIRMWARE);
+    blob[2] = keymap_blob::FORMAT_VERSION + 1;
+    assert!(keymap_blob::decode(&blob).is_none());
+}
+
+#[test]
+fn truncated_blob_is_rejected() {
+    let blob = encode(FIRMWARE);
+    assert!(keymap_blob::decode(&blob[..blob.len() - 1]).is_none());
+    assert!(keymap_blob::decode(&blob[..3]).is_none());
+}
+
+#[test]
+fn smaller_keymap_fills_its_own_positions() {
+    let shape = KeymapShape {
+        left_layers: 1,
+        right_layers: 2,
+        layer_len: 20,
+    };
+    let loaded = loaded(&encode(shape));
+    assert_eq!(loaded.len(), 3 * 20);
+    for (position, key) in loaded {
+        assert_eq!(key, key_at(position));
+    }
+}
+
+#[test]
+fn larger_keymap_loads_the_overlap() {
+    let shape = KeymapShape {
+        left_layers: 6,
+        right_layers: 7,
+        layer_len: 32,
+    };
+    let loaded = loaded(&encode(shape));
+    assert_eq!(loaded.len(), FIRMWARE.entry_count());
+    for (position, key) in loaded {
+        assert_eq!(key, key_at(position));
+    }
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use common::MockFlash;
+use embedded_storage::nor_flash::NorFlash;
+use shared_src::boot::{crc32, FLASH_START, KEYMAP_ADDRESS, SETTINGS_ADDRESS};
+use shared_src::config_protocol::{KeyKind, RawKey};
+use shared_src::eeprom::Eeprom;
+use shared_src::keymap_blob::{self, KeymapShape};
+use shared_src::storage::{self, keys, VERSION};
+
+const FLASH_LEN: usize = 128 * 1024;
+const KEYMAP: usize = (KEYMAP_ADDRESS - FLASH_START) as usize;
+const SETTINGS: usize = (SETTINGS_ADDRESS - FLASH_START) as usize;
+
+/// Keymap blob of format 1 with one left layer of two keys: A and a key kind
+/// unknown to this firmware
+fn v1_keymap_blob() -> Vec<u8> {
+    let mut blob = vec![0x4D, 0x4B, 0x01, 0x01, 0x00, 0x02];
+    blob.extend_from_slice(&[0x00, 0x04, 0x00]);
+    blob.extend_from_slice(&[0x7F, 0x01, 0x00]);
+    let crc = crc32(&blob);
+    blob.extend_from_slice(&crc.to_le_bytes());
+    blob
+}
+
+fn read_setting(eeprom: &Eeprom, flash: &mut MockFlash, key: u8) -> Option<u8> {
+    let mut value = [0; 1];
+    eeprom
+        .read(flash, key, &mut value)
+        .unwrap()
+        .map(|_| value[0])
+}
+
+fn stored_keymap(flash: &MockFlash) -> Option<(KeymapShape, Vec<Option<RawKey>>)> {
+    keymap_blob::decode(&flash.data[KEYMAP..KEYMAP + 1024])
+        .map(|(shape, entries)| (shape, entries.collect()))
+}
+
+#[test]
+fn blank_flash_gets_the_current_version() {
+    let mut flash = MockFlash::new(FLASH_LEN);
+    let eeprom = storage::open(&mut flash).unwrap();
+    assert_eq!(
+        read_setting(&eeprom, &mut flash, keys::VERSION),
+        Some(VERSION)
+    );
+    assert_eq!(read_setting(&eeprom, &mut flash, keys::KEYBOARD_MODE), None);
+    assert_eq!(stored_keymap(&flash), None);
+}
+
+#[test]
+fn old_keymap_blob_loads() {
+    let blob = v1_keymap_blob();
+    let (shape, entries) = keymap_blob::decode(&blob).unwrap();
+    assert_eq!(
+        shape,
+        KeymapShape {
+            left_layers: 1,
+            right_layers: 0,
+            layer_len: 2,
+        }
+    );
+    // The unknown kind leaves the default key to the firmware
+    assert_eq!(
+        entries.collect::<Vec<_>>(),
+        [Some(RawKey::new(KeyKind::Keyboard, 0x04)), None]
+    );
+}
+
+#[test]
+fn older_version_is_migrated() {
+    let mut flash = MockFlash::new(FLASH_LEN);
+    let mut eeprom = Eeprom::mount(&mut flash, SETTINGS as u32, 1024).unwrap();
+    eeprom.write(&mut flash, keys::VERSION, &[0]).unwrap();
+    eeprom.write(&mut flash, keys::KEYBOARD_MODE, &[1]).unwrap();
+
+    let eeprom = storage::open(&mut flash).unwrap();
+    assert_eq!(
+        read_setting(&eeprom, &mut flash, keys::VERSION),
+        Some(VERSION)
+    );
+    assert_eq!(
+        read_setting(&eeprom, &mut flash, keys::KEYBOARD_MODE),
+        Some(1)
+    );
+
+    // Nothing left to do on the next start
+    let operations = flash.operations;
+    storage::open(&mut flash).unwrap();
+    assert_eq!(flash.operations, operations);
+}
+
+#[test]
+fn settings_without_version_record_are_kept() {
+    let mut flash = MockFlash::new(FLASH_LEN);
+    let mut eeprom = Eeprom::mount(&mut flash, SETTINGS as u32, 1024).unwrap();
+    eeprom.write(&mut flash, keys::KEYBOARD_MODE, &[1]).unwrap();
+
+    let eeprom = storage::open(&mut flash).unwrap();
+    assert_eq!(
+        read_setting(&eeprom, &mut flash, keys::VERSION),
+        Some(VERSION)
+    );
+    assert_eq!(
+        read_setting(&eeprom, &mut flash, keys::KEYBOARD_MODE),
+        Some(1)
+    );
+}
+
+#[test]
+fn newer_version_is_left_alone() {
+    let mut flash = MockFlash::new(FLASH_LEN);
+    let mut eeprom = Eeprom::mount(&mut flash, SETTINGS as u32, 1024).unwrap();
+    eeprom
+        .write(&mut flash, keys::VERSION, &[VERSION + 1])
+        .unwrap();
+    eeprom.write(&mut flash, keys::KEYBOARD_MODE, &[0]).unwrap();
+
+    let eeprom = storage::open(&mut flash).unwrap();
+    assert_eq!(
+        read_setting(&eeprom, &mut flash, keys::VERSION),
+        Some(VERSION + 1)
+    );
+    assert_eq!(
+        read_setting(&eeprom, &mut flash, keys::KEYBOARD_MODE),
+        Some(0)
+    );
+}
+
+#[test]
+fn factory_reset_clears_everything() {
+    let mut flash = MockFlash::new(FLASH_LEN);
+    let mut eeprom = storage::open(&mut flash).unwrap();
+    eeprom.write(&mut flash, keys::KEYBOARD_MODE, &[1]).unwrap();
+    flash.write(KEYMAP as u32, &v1_keymap_blob()).unwrap();
+    assert!(stored_keymap(&flash).is_some());
+
+    storage::factory_reset(&mut flash).unwrap();
+    let eeprom = storage::open(&mut flash).unwrap();
+    assert_eq!(read_setting(&eeprom, &mut flash, keys::KEYBOARD_MODE), None);
+    assert_eq!(stored_keymap(&flash), None);
+}
@@ -0,0 +1,105 @@
+//! Virtual host: keeps the lock LEDs and types the text of the keyboard
+//! reports with a US layout. Keys pressed with Ctrl, Alt or GUI held are
+//! shortcuts and type nothing, there is no auto-repeat.
+
+use shared_src::HostLeds;
+use usbd_human_interface_device::page::Keyboard;
+
+/// Keys typing a character, without and with Shift
+#[rustfmt::skip]
+const SYMBOLS: &[(Keyboard, char, char)] = &[
+    (Keyboard::Keyboard1, '1', '!'), (Keyboard::Keyboard2, '2', '@'),
+    (Keyboard::Keyboard3, '3', '#'), (Keyboard::Keyboard4, '4', '$'),
+    (Keyboard::Keyboard5, '5', '%'), (Keyboard::Keyboard6, '6', '^'),
+    (Keyboard::Keyboard7, '7', '&'), (Keyboard::Keyboard8, '8', '*'),
+    (Keyboard::Keyboard9, '9', '('), (Keyboard::Keyboard0, '0', ')'),
+    (Keyboard::ReturnEnter, '\n', '\n'), (Keyboard::Tab, '\t', '\t'),
+    (Keyboard::Space, ' ', ' '), (Keyboard::Minus, '-', '_'),
+    (Keyboard::Equal, '=', '+'), (Keyboard::LeftBrace, '[', '{'),
+    (Keyboard::RightBrace, ']', '}'), (Keyboard::Backslash, '\\', '|'),
+    (Keyboard::Semicolon, ';', ':'), (Keyboard::Apostrophe, '\'', '"'),
+    (Keyboard::Grave, '`', '~'), (Keyboard::Comma, ',', '<'),
+    (Keyboard::Dot, '.', '>'), (Keyboard::ForwardSlash, '/', '?'),
+    (Keyboard::KeypadDivide, '/', '/'), (Keyboard::KeypadMultiply, '*', '*'),
+    (Keyboard::KeypadSubtract, '-', '-'), (Keyboard::KeypadAdd, '+', '+'),
+    (Keyboard::KeypadEnter, '\n', '\n'),
+];
+
+/// Keypad keys typing a character while Num Lock is on
+#[rustfmt::skip]
+const KEYPAD: &[(Keyboard, char)] = &[
+    (Keyboard::Keypad1, '1'), (Keyboard::Keypad2, '2'), (Keyboard::Keypad3, '3'),
+    (Keyboard::Keypad4, '4'), (Keyboard::Keypad5, '5'), (Keyboard::Keypad6, '6'),
+    (Keyboard::Keypad7, '7'), (Keyboard::Keypad8, '8'), (Keyboard::Keypad9, '9'),
+    (Keyboard::Keypad0, '0'), (Keyboard::KeypadDot, '.'),
+];
+
+const SHIFT: [Keyboard; 2] = [Keyboard::LeftShift, Keyboard::RightShift];
+#[rustfmt::skip]
+const SHORTCUT_MODIFIERS: [Keyboard; 6] = [
+    Keyboard::LeftControl, Keyboard::RightControl,
+    Keyboard::LeftAlt, Keyboard::RightAlt,
+    Keyboard::LeftGUI, Keyboard::RightGUI,
+];
+
+#[derive(Default)]
+pub struct Host {
+    pub leds: HostLeds,
+    pub text: String,
+    /// Keys of the last report
+    held: Vec<Keyboard>,
+}
+
+impl Host {
+    /// Takes a keyboard report, returns whether the LEDs changed
+    pub fn keyboard_report(&mut self, keys: &[Keyboard]) -> bool {
+        // A rollover report tells that the keys are unknown, the host keeps
+        // the previous ones
+        if keys.contains(&Keyboard::ErrorRollOver) {
+            return false;
+        }
+
+        let held = |modifiers: &[Keyboard]| keys.iter().any(|key| modifiers.contains(key));
+        let shift = held(&SHIFT);
+        let shortcut = held(&SHORTCUT_MODIFIERS);
+        let leds = self.leds;
+        for &key in keys.iter().filter(|key| !self.held.contains(key)) {
+            match key {
+                Keyboard::CapsLock => self.leds.0 ^= HostLeds::CAPS_LOCK,
+                Keyboard::KeypadNumLockAndClear => self.leds.0 ^= HostLeds::NUM_LOCK,
+                Keyboard::ScrollLock => self.leds.0 ^= HostLeds::SCROLL_LOCK,
+                _ if shortcut => {}
+                Keyboard::DeleteBackspace => {
+                    self.text.pop();
+                }
+                _ => self.text.extend(self.character(key, shift)),
+            }
+        }
+        self.held = keys.to_vec();
+        self.leds != leds
+    }
+
+    fn character(&self, key: Keyboard, shift: bool) -> Option<char> {
+        // A to Z follow each other in the usage table
+        let code = u8::from(key);
+        if (u8::from(Keyboard::A)..=u8::from(Keyboard::Z)).contains(&code) {
+            let letter = char::from(b'a' + code - u8::from(Keyboard::A));
+            return Some(match shift != self.leds.get(HostLeds::CAPS_LOCK) {
+                true => letter.to_ascii_uppercase(),
+                false => letter,
+            });
+        }
+
+        let keypad = KEYPAD
+            .iter()
+            .filter(|_| self.leds.get(HostLeds::NUM_LOCK))
+            .find(|&&(known, _)| known == key)
+            .map(|&(_, character)| character);
+        keypad.or_else(|| {
+            SYMBOLS
+                .iter()
+                .find(|&&(known, ..)| known == key)
+                .map(|&(_, normal, shifted)| if shift { shifted } else { normal })
+        })
+    }
+}
@@ -0,0 +1,280 @@
+//! Keyboard simulator: runs the keymap engine of the left half
+//! (`keyboard_core`) on a script of matrix events (see `script.rs`) and prints
+//! the HID reports a host would receive and the text they type.
+//!
+//! The engine is called every millisecond, as often as the matrix of the
+//! right half arrives, followed by a tick of the mouse keys. The virtual host
+//! sends its lock LEDs back, so Num Lock switches the layers bound to it.
+
+mod host;
+mod script;
+
+use host::Host;
+use keyboard_core::fixed_vec::FixedVec;
+use keyboard_core::layouts_def::{self, KeyCommand, KeyboardMode, MultiKey};
+use keyboard_core::mouse_keys::MouseKeys;
+use keyboard_core::system_control::SystemControl;
+use keymap_tool::{layer_context, Error, Keymap, Side, MATRIX_LEN, NO_EVENT};
+use script::Event;
+use shared_src::config_protocol::{KeyKind, KeyPosition, RawKey};
+use shared_src::{keycodes, HostLeds, PrimitiveBitset};
+use std::fmt::Write;
+use std::path::Path;
+use std::{env, fs, process};
+use usbd_human_interface_device::device::mouse::WheelMouseReport;
+use usbd_human_interface_device::page::{Consumer, Keyboard};
+
+const USAGE: &str = "\
+usage: simulator <script> [keymap.toml]
+
+Runs the script against the keymap built into the firmware, or the given
+keymap file";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (script, keymap) = match args.as_slice() {
+        [script] => (Path::new(script), None),
+        [script, keymap] => (Path::new(script), Some(Path::new(keymap))),
+        _ => {
+            eprintln!("{USAGE}");
+            process::exit(2);
+        }
+    };
+
+    match run(script, keymap) {
+        Ok(output) => print!("{output}"),
+        Err(error) => {
+            eprintln!("error: {error}");
+            process::exit(1);
+        }
+    }
+}
+
+fn run(script: &Path, keymap: Option<&Path>) -> Result<String, Error> {
+    if let Some(path) = keymap {
+        load_keymap(&Keymap::load(path)?)?;
+    }
+    let text = fs::read_to_string(script)
+        .map_err(|error| Error(format!("{}: {error}", script.display())))?;
+    let events = script::parse(&text, find_key)
+        .map_err(|error| Error(format!("{}: {error}", script.display())))?;
+    Ok(simulate(&events))
+}
+
+/// Replaces the built-in keymap, as the configuration channel would
+fn load_keymap(keymap: &Keymap) -> Result<(), Error> {
+    let shape = layouts_def::KEYMAP_SHAPE;
+    if keymap.left.len() != shape.left_layers as usize
+        || keymap.right.len() != shape.right_layers as usize
+    {
+        return Err(Error(format!(
+            "the firmware has {} left and {} right layers, the keymap {} and {}",
+            shape.left_layers,
+            shape.right_layers,
+            keymap.left.len(),
+            keymap.right.len()
+        )));
+    }
+
+    for side in Side::ALL {
+        for (layer, keys) in keymap.layers(side).iter().enumerate() {
+            for (index, key) in keys.matrix(side).into_iter().enumerate() {
+                let position = KeyPosition {
+                    side: side.index(),
+                    layer: layer as u8,
+                    index: index as u8,
+                };
+                let key = MultiKey::from_raw(key).ok_or_else(|| {
+                    Error(format!(
+                        "{}: position {index} has no firmware key",
+                        layer_context(side, layer, &keys.name)
+                    ))
+                })?;
+                // The shape was checked above
+                layouts_def::set_key(position, key).unwrap_or_else(|_| unreachable!());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Matrix position of a key of the base layers
+fn find_key(name: &str) -> Result<(Side, usize), String> {
+    let key = keycodes::parse(name).map_err(|error| format!("{error} `{name}`"))?;
+    if key == NO_EVENT {
+        return Err(format!("`{name}` is no key, give the position, e.g. L25"));
+    }
+
+    let found: Vec<(Side, usize)> = Side::ALL
+        .into_iter()
+        .flat_map(|side| (0..MATRIX_LEN).map(move |index| (side, index)))
+        .filter(|&(side, index)| {
+            let position = KeyPosition {
+                side: side.index(),
+                layer: 0,
+                index: index as u8,
+            };
+            layouts_def::get_key(position).is_ok_and(|base| base.to_raw() == key)
+        })
+        .collect();
+    match found.as_slice() {
+        [position] => Ok(*position),
+        [] => Err(format!("`{name}` is not on the base layers")),
+        _ => Err(format!(
+            "`{name}` is on the base layers {} times, give the position",
+            found.len()
+        )),
+    }
+}
+
+/// Runs the events, one line of output per change the host sees
+fn simulate(events: &[Event]) -> String {
+    let mut output = String::new();
+    let mut log = |ms: u32, line: String| writeln!(output, "{ms:>6} ms  {line}").unwrap();
+
+    let mut left_matrix = PrimitiveBitset::new(0u32);
+    let mut right_matrix = PrimitiveBitset::new(0u32);
+    let mut mode = KeyboardMode::Nkro;
+    let mut host = Host::default();
+
+    let mut key_report: FixedVec<_, 58> = FixedVec::new(Keyboard::NoEventIndicated);
+    let mut media_report: FixedVec<_, 4> = FixedVec::new(Consumer::Unassigned);
+    let mut system_report = SystemControl::None;
+    let mut mouse_keys = MouseKeys::new();
+
+    // What the host received last, reports are sent when they change
+    let mut sent_keys: Vec<Keyboard> = Vec::new();
+    let mut sent_media: Vec<Consumer> = Vec::new();
+    let mut sent_system = SystemControl::None;
+    let mut layers = (0, 0);
+
+    let end = events.last().map_or(0, |event| event.time);
+    let mut pending = events.iter().peekable();
+    for ms in 0..=end {
+        while let Some(event) = pending.next_if(|event| event.time == ms) {
+            match event.side {
+                Side::Left => left_matrix.set(event.index, event.pressed),
+                Side::Right => right_matrix.set(event.index, event.pressed),
+            }
+        }
+
+        if layouts_def::get_report(
+            left_matrix,
+            right_matrix,
+            host.leds,
+            mode,
+            &mut key_report,
+            &mut media_report,
+            &mut system_report,
+            &mut mouse_keys,
+        ) {
+            if layouts_def::active_layers() != layers {
+                layers = layouts_def::active_layers();
+                log(ms, format!("layers: left {}, right {}", layers.0, layers.1));
+            }
+            if key_report[..] != sent_keys[..] {
+                sent_keys = key_report.to_vec();
+                log(ms, format!("keyboard: {}", keyboard_names(&sent_keys)));
+                if host.keyboard_report(&sent_keys) {
+                    log(ms, format!("host leds: {}", led_names(host.leds)));
+                }
+            }
+            if media_report[..] != sent_media[..] {
+                sent_media = media_report.to_vec();
+                log(ms, format!("consumer: {}", consumer_names(&sent_media)));
+            }
+            if system_report != sent_system {
+                sent_system = system_report;
+                let name = name(KeyKind::System, sent_system as u16);
+                let name = name.as_deref().unwrap_or("(none)");
+                log(ms, format!("system: {name}"));
+            }
+        }
+
+        match layouts_def::take_command() {
+            Some(KeyCommand::ToggleKeyboardMode) => {
+                mode = match mode {
+                    KeyboardMode::Nkro => KeyboardMode::SixKeyRollover,
+                    KeyboardMode::SixKeyRollover => KeyboardMode::Nkro,
+                };
+                let name = match mode {
+                    KeyboardMode::Nkro => "NKRO",
+                    KeyboardMode::SixKeyRollover => "6KRO",
+                };
+                log(ms, format!("keyboard mode: {name}"));
+            }
+            Some(command) => {
+                let name = name(KeyKind::Command, command as u16).unwrap_or_default();
+                log(ms, format!("command: {name}"));
+            }
+            None => {}
+        }
+
+        if let Some(report) = mouse_keys.tick() {
+            log(ms, format!("mouse: {}", mouse_report(&report)));
+        }
+    }
+
+    writeln!(output, "text: {:?}", host.text).unwrap();
+    output
+}
+
+/// Name of the key of the keymap file, without the kind
+fn name(kind: KeyKind, code: u16) -> Option<String> {
+    keycodes::name(RawKey::new(kind, code)).map(|name| match kind {
+        KeyKind::Keyboard => name.to_string(),
+        _ => name.variant.to_string(),
+    })
+}
+
+fn keyboard_names(keys: &[Keyboard]) -> String {
+    names(keys.iter().map(|&key| {
+        let code = u8::from(key);
+        name(KeyKind::Keyboard, code as u16).unwrap_or_else(|| format!("{code:#04x}"))
+    }))
+}
+
+fn consumer_names(keys: &[Consumer]) -> String {
+    names(keys.iter().map(|&key| {
+        let code = u16::from(key);
+        name(KeyKind::Consumer, code).unwrap_or_else(|| format!("{code:#06x}"))
+    }))
+}
+
+fn led_names(leds: HostLeds) -> String {
+    let lit = [
+        (HostLeds::NUM_LOCK, "NumLock"),
+        (HostLeds::CAPS_LOCK, "CapsLock"),
+        (HostLeds::SCROLL_LOCK, "ScrollLock"),
+    ]
+    .into_iter()
+    .filter(|&(led, _)| leds.get(led))
+    .map(|(_, name)| name.to_string());
+    names(lit)
+}
+
+fn mouse_report(report: &WheelMouseReport) -> String {
+    let buttons = keycodes::MOUSE_BUTTON
+        .iter()
+        .filter(|&&(_, code)| report.buttons & (1 << code) != 0)
+        .map(|&(name, _)| format!("button {name}"));
+    let axes = [
+        ("x", report.x),
+        ("y", report.y),
+        ("wheel", report.vertical_wheel),
+        ("pan", report.horizontal_wheel),
+    ]
+    .into_iter()
+    .filter(|&(_, value)| value != 0)
+    .map(|(axis, value)| format!("{axis} {value}"));
+    names(buttons.chain(axes))
+}
+
+/// Items separated by spaces, `(none)` if there are none
+fn names(items: impl Iterator<Item = String>) -> String {
+    let names: Vec<String> = items.collect();
+    match names.is_empty() {
+        true => String::from("(none)"),
+        false => names.join(" "),
+    }
+}
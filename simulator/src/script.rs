@@ -0,0 +1,98 @@
+//! Scripts of matrix events. Every line holds a time in milliseconds, an
+//! action and the keys it applies to, `#` starts a comment:
+//!
+//! ```text
+//! 0    press    LSft
+//! +20  tap      H      # pressed, released TAP_MS later
+//! 100  release  LSft
+//! 150  press    L25 R26
+//! ```
+//!
+//! A time is absolute, or relative to the previous line with `+`. A key is a
+//! matrix position, `L<index>` or `R<index>`, or a key spelled as in the
+//! keymap file that appears once on the base layers of both halves.
+
+use keymap_tool::{Error, Side, MATRIX_LEN};
+
+/// How long `tap` holds its keys
+pub const TAP_MS: u32 = 20;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Event {
+    pub time: u32,
+    pub side: Side,
+    pub index: usize,
+    pub pressed: bool,
+}
+
+/// Events of the script sorted by time, `find_key` gives the matrix position
+/// of a key name
+pub fn parse(
+    text: &str,
+    find_key: impl Fn(&str) -> Result<(Side, usize), String>,
+) -> Result<Vec<Event>, Error> {
+    let mut events = Vec::new();
+    let mut time = 0;
+    for (n, line) in text.lines().enumerate() {
+        let error = |message: String| Error(format!("line {}: {message}", n + 1));
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        let Some(at) = words.next() else {
+            continue;
+        };
+
+        time = match at.strip_prefix('+') {
+            Some(delay) => time + parse_ms(delay).map_err(error)?,
+            None => match parse_ms(at).map_err(error)? {
+                at if at < time => {
+                    return Err(error(format!("{at} ms is before the previous line")))
+                }
+                at => at,
+            },
+        };
+        let steps: &[(u32, bool)] = match words.next() {
+            Some("press") => &[(0, true)],
+            Some("release") => &[(0, false)],
+            Some("tap") => &[(0, true), (TAP_MS, false)],
+            Some(action) => return Err(error(format!("unknown action `{action}`"))),
+            None => return Err(error(String::from("missing action"))),
+        };
+        let keys = words
+            .map(|word| position(word).map_or_else(|| find_key(word), Ok))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(error)?;
+        if keys.is_empty() {
+            return Err(error(String::from("missing keys")));
+        }
+
+        for &(delay, pressed) in steps {
+            for &(side, index) in &keys {
+                events.push(Event {
+                    time: time + delay,
+                    side,
+                    index,
+                    pressed,
+                });
+            }
+        }
+    }
+    // Stable, events of the same time keep the order of the script
+    events.sort_by_key(|event| event.time);
+    Ok(events)
+}
+
+fn parse_ms(word: &str) -> Result<u32, String> {
+    word.parse()
+        .map_err(|_| format!("expected a time in milliseconds, found `{word}`"))
+}
+
+/// Matrix position spelled `L<index>` or `R<index>`
+fn position(word: &str) -> Option<(Side, usize)> {
+    let (side, index) = match word.split_at_checked(1)? {
+        ("L", index) => (Side::Left, index),
+        ("R", index) => (Side::Right, index),
+        _ => return None,
+    };
+    let index = index.parse().ok().filter(|&index| index < MATRIX_LEN)?;
+    Some((side, index))
+}
@@ -0,0 +1,52 @@
+//! Runs every script of `tests/scripts` through the simulator and compares
+//! the output with the `.out` file next to it. `UPDATE_GOLDEN=1 cargo test`
+//! rewrites the `.out` files instead, review their diff before committing.
+//!
+//! Each script runs in a process of its own, the engine keeps its state in
+//! statics.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn scripts_match_golden_output() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts");
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut scripts: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no scripts in {}", dir.display());
+
+    let mut mismatches = Vec::new();
+    for script in &scripts {
+        let run = Command::new(env!("CARGO_BIN_EXE_simulator"))
+            .arg(script)
+            .output()
+            .unwrap();
+        assert!(
+            run.status.success(),
+            "{}: {}",
+            script.display(),
+            String::from_utf8_lossy(&run.stderr)
+        );
+        let output = String::from_utf8(run.stdout).unwrap();
+
+        let golden = script.with_extension("out");
+        if update {
+            fs::write(&golden, &output).unwrap();
+        } else if fs::read_to_string(&golden).ok().as_deref() != Some(output.as_str()) {
+            eprintln!("{}:\n{output}", golden.display());
+            mismatches.push(golden);
+        }
+    }
+    assert!(
+        mismatches.is_empty(),
+        "output differs from {mismatches:?}, rerun with UPDATE_GOLDEN=1 if expected"
+    );
+}
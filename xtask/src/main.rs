@@ -0,0 +1,89 @@
+//! Workspace tasks, run as `cargo xtask <task>`.
+
+use std::path::Path;
+use std::process::{self, Command};
+use std::{env, fmt};
+
+const USAGE: &str = "\
+usage: cargo xtask <task> [cargo options]
+
+tasks:
+  firmware   build the firmware of both halves and the bootloader, release
+  test       run the host tests
+  ci         both, what a change to the shared crates has to pass
+
+The options, e.g. --offline or --locked, are passed to every cargo command";
+
+struct Firmware {
+    package: &'static str,
+    target: &'static str,
+    features: &'static str,
+}
+
+/// Every firmware with the target of its MCU, features are built as variants
+/// of their own
+#[rustfmt::skip]
+const FIRMWARES: &[Firmware] = &[
+    Firmware { package: "left-stm32f1-bootloader", target: "thumbv7m-none-eabi", features: "" },
+    Firmware { package: "left-stm32f1", target: "thumbv7m-none-eabi", features: "" },
+    Firmware { package: "left-stm32f1", target: "thumbv7m-none-eabi", features: "console" },
+    Firmware { package: "right-stm32f4", target: "thumbv7em-none-eabihf", features: "" },
+];
+
+struct Failed(String);
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "`{}` failed", self.0)
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let (task, options) = args.split_first().unwrap_or((&"", &[]));
+    let result = match *task {
+        "firmware" => firmware(options),
+        "test" => test(options),
+        "ci" => firmware(options).and_then(|()| test(options)),
+        _ => {
+            eprintln!("{USAGE}");
+            process::exit(2);
+        }
+    };
+    if let Err(error) = result {
+        eprintln!("error: {error}");
+        process::exit(1);
+    }
+}
+
+fn firmware(options: &[&str]) -> Result<(), Failed> {
+    for firmware in FIRMWARES {
+        let mut args = vec!["build", "--release", "--package", firmware.package];
+        args.extend(["--target", firmware.target]);
+        if !firmware.features.is_empty() {
+            args.extend(["--features", firmware.features]);
+        }
+        args.extend(options);
+        cargo(&args)?;
+    }
+    Ok(())
+}
+
+/// Tests of the default members, the host crates
+fn test(options: &[&str]) -> Result<(), Failed> {
+    cargo(&[&["test"], options].concat())
+}
+
+fn cargo(args: &[&str]) -> Result<(), Failed> {
+    let command = format!("cargo {}", args.join(" "));
+    eprintln!("==> {command}");
+
+    let cargo = env::var("CARGO").unwrap_or_else(|_| String::from("cargo"));
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    let status = Command::new(cargo).args(args).current_dir(root).status();
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        _ => Err(Failed(command)),
+    }
+}